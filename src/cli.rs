@@ -1,6 +1,10 @@
 use crate::commands::ask;
 use crate::commands::chat;
+use crate::commands::config;
+use crate::commands::policy;
 use crate::commands::run;
+use crate::commands::usage::{self, GroupBy};
+use crate::core::config::{Config, OutputFormat, Source};
 use clap::{Parser, Subcommand};
 
 #[derive(Parser, Debug)]
@@ -12,6 +16,18 @@ pub struct Cli {
 
     #[arg(short, long, global = true)]
     verbose: bool,
+
+    /// Model to use, e.g. gpt-4o-mini, claude-3-haiku-20240307, gemini-2.0-flash
+    #[arg(long, global = true)]
+    model: Option<String>,
+
+    /// Kill commands that run longer than this many seconds
+    #[arg(long, global = true, value_name = "SECS")]
+    timeout: Option<u64>,
+
+    /// Print a JSON document for scripts instead of text (ask and run only)
+    #[arg(long, global = true, value_enum, value_name = "FORMAT")]
+    output: Option<OutputFormat>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -34,22 +50,195 @@ enum Commands {
         /// without permitions
         #[arg(short, default_value_t = false)]
         y: bool,
+
+        /// Maximum number of model round-trips before giving up
+        #[arg(long)]
+        max_steps: Option<usize>,
+
+        /// Maximum bytes of stdout/stderr kept per command and sent to the model
+        #[arg(long)]
+        max_output: Option<usize>,
+
+        /// Attach a file, or the text files of a directory, to the prompt
+        #[arg(short, long = "file", value_name = "PATH")]
+        files: Vec<String>,
+
+        /// Do not start the prompt with a summary of the OS, project and tools
+        #[arg(long)]
+        no_context: bool,
+
+        /// Do not read piped stdin, e.g. when a parent process leaves it open
+        #[arg(long)]
+        no_stdin: bool,
+    },
+    /// Chat with the model; conversations are saved and can be resumed
+    Chat {
+        /// Continue a saved session: its id, an id prefix or `last`
+        #[arg(long, value_name = "ID")]
+        resume: Option<String>,
+
+        /// List saved sessions
+        #[arg(long, conflicts_with_all = ["resume", "delete"])]
+        list: bool,
+
+        /// Delete a saved session
+        #[arg(long, value_name = "ID", conflicts_with = "resume")]
+        delete: Option<String>,
+
+        /// Let the model run commands, with the same checks and approvals as `ask`
+        #[arg(long)]
+        tools: bool,
+
+        /// Attach a file, or the text files of a directory, to the first message
+        #[arg(short, long = "file", value_name = "PATH")]
+        files: Vec<String>,
+
+        /// Do not read piped stdin, e.g. when a parent process leaves it open
+        #[arg(long)]
+        no_stdin: bool,
+
+        /// without permitions
+        #[arg(short, default_value_t = false)]
+        y: bool,
+    },
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Inspect the security policy
+    Policy {
+        #[command(subcommand)]
+        action: PolicyAction,
+    },
+    /// Summarize recorded token usage and cost
+    Usage {
+        /// Only count calls from this long ago, e.g. 12h, 7d or 2w
+        #[arg(long, value_name = "DURATION")]
+        since: Option<String>,
+
+        /// Group the totals by model, day or command
+        #[arg(long, value_enum, default_value_t = GroupBy::Model)]
+        by: GroupBy,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    /// Print the effective configuration and where each value came from
+    Show,
+}
+
+#[derive(Subcommand, Debug)]
+enum PolicyAction {
+    /// Show which rule matches a command and whether it would run
+    Check {
+        /// The command to check
+        #[arg(required = true)]
+        command: String,
+
+        /// Decide as if -y was given
+        #[arg(short, default_value_t = false)]
+        y: bool,
     },
-    Chat {},
 }
 
 pub async fn run_cli(cli: Cli) {
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+    if let Some(model) = cli.model {
+        config.model.set(model, Source::Flag);
+    }
+    if let Some(timeout) = cli.timeout {
+        config.timeout.set(Some(timeout), Source::Flag);
+    }
+    if let Some(output) = cli.output {
+        if output == OutputFormat::Json
+            && !matches!(cli.command, Commands::Run { .. } | Commands::Ask { .. })
+        {
+            eprintln!("--output json is only supported by `aic ask` and `aic run`");
+            return;
+        }
+        config.output.set(output, Source::Flag);
+    }
+
     match cli.command {
         Commands::Run { command, y } => {
-            run::handle_command_execution(&command, y);
+            if y {
+                config.yes.set(true, Source::Flag);
+            }
+            run::handle_command_execution(&command, &config);
         }
-        Commands::Ask { prompt, y } => {
-            ask::handle_prompt_req(&prompt, y)
+        Commands::Ask {
+            prompt,
+            y,
+            max_steps,
+            max_output,
+            files,
+            no_context,
+            no_stdin,
+        } => {
+            if y {
+                config.yes.set(true, Source::Flag);
+            }
+            if let Some(max_steps) = max_steps {
+                config.max_steps.set(max_steps, Source::Flag);
+            }
+            if let Some(max_output) = max_output {
+                config.max_output.set(max_output, Source::Flag);
+            }
+            if no_context {
+                config.env_context.set(false, Source::Flag);
+            }
+            if no_stdin {
+                config.max_input.set(0, Source::Flag);
+            }
+            ask::handle_prompt_req(&prompt, &files, &config)
                 .await
                 .unwrap_or_else(|e| eprintln!("{e}"));
         }
-        Commands::Chat {} => {
-            chat::chat().await.unwrap_or_else(|e| eprintln!("{e}"));
+        Commands::Chat {
+            resume,
+            list,
+            delete,
+            tools,
+            files,
+            no_stdin,
+            y,
+        } => {
+            if y {
+                config.yes.set(true, Source::Flag);
+            }
+            if no_stdin {
+                config.max_input.set(0, Source::Flag);
+            }
+            let result = if list {
+                chat::list_sessions()
+            } else if let Some(id) = delete {
+                chat::delete_session(&id)
+            } else {
+                chat::chat(&config, resume.as_deref(), tools, &files).await
+            };
+            result.unwrap_or_else(|e| eprintln!("{e}"));
+        }
+        Commands::Config { action } => match action {
+            ConfigAction::Show => config::show(&config),
+        },
+        Commands::Policy { action } => match action {
+            PolicyAction::Check { command, y } => {
+                if y {
+                    config.yes.set(true, Source::Flag);
+                }
+                policy::check(&command, &config);
+            }
+        },
+        Commands::Usage { since, by } => {
+            usage::show(since.as_deref(), by).unwrap_or_else(|e| eprintln!("{e}"))
         }
     }
 }
@@ -1,9 +1,16 @@
-use crate::core::ai_client::{get_ai_client, MODEL};
-use crate::core::executor;
-use crate::utils::security::{self, ValidationError};
-use serde_json::Value;
+use crate::commands::output;
+use crate::core::ai_client::get_ai_client;
+use crate::core::config::Config;
+use crate::core::executor::{self, CommandOutput, CommandStatus, ExecContext};
+use crate::core::usage::{self, Tally};
+use crate::utils::attach::{self, Attachments};
+use crate::utils::input;
+use crate::utils::markdown;
+use crate::utils::security::{self, Assessment, Decision, ValidationError};
+use genai::chat::{ChatMessage, MessageContent, ToolCall, ToolResponse};
+use serde_json::{json, Value};
 use std::io::Write;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use std::path::Path;
 
 pub const SYSTEM_PROMPT: &str = r#"
 # AICommander System Prompt
@@ -63,6 +70,8 @@ pub fn execute(args: &str) -> Result<CommandOutput, CommandOutput>
 1. **Always execute a command first** using the tool
 2. **Then provide interpretation** of the results
 3. **Suggest next steps** if relevant
+4. **Read the tool result**: every command's exit code, stdout and stderr are sent back to you; call the tool again if more steps are needed
+5. **Finish with plain text**: once the task is done, reply without a tool call to give the final answer
 
 ### Response Template:
 ```
@@ -81,8 +90,9 @@ Note: Executed safer alternative because [brief reason]
 
 ## Context Intelligence
 
-**Auto-detect project environment:**
-- Scan for `package.json`, `Cargo.toml`, `requirements.txt`, `composer.json`
+**Use the project environment:**
+- The request may start with an "Environment" summary collected by aic: OS, shell, working directory, project stacks detected from manifests such as `package.json` or `Cargo.toml`, git branch and state, and tool versions
+- Trust it instead of running commands to rediscover the same facts
 - Adapt commands based on detected stack (Node.js, Rust, Python, PHP, etc.)
 - Remember recent command context within session
 
@@ -176,81 +186,279 @@ npm start &> server.log & echo "Server started, PID: $!" && sleep 2 && ps aux |
 
 ## Session Memory
 
-- Track working directory changes
+- `cd` and `export` carry over to later commands; each result reports the `cwd` the next command runs in
 - Remember recently used commands and patterns
 - Build on previous successful operations
 - Note user preferences and skill level
 
-Remember: Your goal is to be a highly effective command-line partner that **ALWAYS uses the execute tool** for every interaction. You must never provide a response without executing a command first. Balance helpfulness with safety by choosing appropriate commands to execute, but execution is mandatory for every single response.    
+Remember: Your goal is to be a highly effective command-line partner that **ALWAYS uses the execute tool** for every interaction. You must never provide a response without executing a command first. Balance helpfulness with safety by choosing appropriate commands to execute, but execution is mandatory for every single response. When the results answer the request, end with a short plain-text summary instead of another tool call.
 "#;
 
-pub async fn get_command(
-    user_prompt: &str,
-) -> Result<(String, String), Box<dyn std::error::Error>> {
-    let (client, chat_req) = get_ai_client(user_prompt, SYSTEM_PROMPT).await?;
+/// Extracts the `command` and `info` arguments of a `run_commandes` tool call.
+fn parse_tool_call(tool_call: &ToolCall) -> Option<(String, String)> {
+    let args = &tool_call.fn_arguments;
+    match (args.get("command"), args.get("info")) {
+        (Some(Value::String(command)), Some(Value::String(info))) => {
+            Some((command.clone(), info.clone()))
+        }
+        (Some(Value::String(command)), None) => Some((command.clone(), String::new())),
+        _ => None,
+    }
+}
+
+/// Asks the user whether `command` may run in `cwd`. Returns `true` when the
+/// user accepts. The question goes to the terminal; without one it falls back
+/// to stdout, or stderr with `--output json` to keep stdout for the document.
+fn confirm_command(
+    command: &str,
+    info: &str,
+    cwd: &Path,
+    assessment: &Assessment,
+    config: &Config,
+) -> bool {
+    const COLOR_RED: &str = "\x1b[31m";
+    const COLOR_YELLOW: &str = "\x1b[33m";
+    const COLOR_BOLD: &str = "\x1b[1m";
+    const COLOR_NC: &str = "\x1b[0m";
+
+    let findings = &assessment.findings;
+    let has = |issue| findings.iter().any(|f| f.issue == issue);
+    let mut reason = if has(ValidationError::DangerousCommand) {
+        format!(
+            "\n{COLOR_BOLD}{COLOR_RED}WARNING: This command is potentially destructive!{COLOR_NC}"
+        )
+    } else if has(ValidationError::ElevatedPrivileges) {
+        format!("\n{COLOR_BOLD}{COLOR_YELLOW}WARNING: This command requires elevated privileges!{COLOR_NC}")
+    } else {
+        String::new()
+    };
+    reason.push_str(&format!("\nRisk: {}", assessment.tier));
+    for finding in findings {
+        reason.push_str(&format!("\n  - {finding}"));
+    }
+    for note in &assessment.notes {
+        reason.push_str(&format!("\n  * {note}"));
+    }
+
+    let prompt = format!(
+        "{reason}\nAre you sure you want to execute:{COLOR_RED} \"{command}\" {COLOR_NC}?\nDescription: {info}\nDirectory: {}\n({COLOR_BOLD}yes{COLOR_NC}/no): ",
+        cwd.display()
+    );
+    let mut fallback: Box<dyn Write> = if config.json() {
+        Box::new(std::io::stderr())
+    } else {
+        Box::new(std::io::stdout())
+    };
+    // Reading blocks; let the runtime move other tasks off this thread.
+    let input = tokio::task::block_in_place(|| input::prompt_line(&prompt, &mut fallback))
+        .unwrap_or_default();
+
+    input.trim().eq_ignore_ascii_case("yes") || input.trim().eq_ignore_ascii_case("y")
+}
+
+/// Formats the result of a command as the tool response sent back to the model,
+/// with the directory the next command will run in.
+fn tool_result(output: &CommandOutput, cwd: &Path) -> String {
+    json!({
+        "cwd": cwd.display().to_string(),
+        "status": output.status.as_str(),
+        "status_code": output.status_code,
+        "stdout": output.stdout.as_deref().unwrap_or_default(),
+        "stderr": output.stderr.as_deref().unwrap_or_default(),
+        "signal": output.signal,
+        "duration_ms": output.duration.as_millis() as u64,
+        "error": output.output_str,
+    })
+    .to_string()
+}
 
-    // println!("--- Getting function call from model");
-    let chat_res = client.exec_chat(MODEL, chat_req.clone(), None).await?;
+/// A handled tool call: the response sent back to the model and the report
+/// for `--output json`.
+pub struct ToolRun {
+    pub response: String,
+    pub report: Value,
+}
 
-    let tool_calls = chat_res
-        .into_tool_calls()
-        .ok_or("Expected tool calls in the response")?;
+/// Validates, confirms and runs a single tool call in `context`.
+pub async fn run_tool_call(
+    tool_call: &ToolCall,
+    config: &Config,
+    context: &mut ExecContext,
+) -> ToolRun {
+    let Some((command, info)) = parse_tool_call(tool_call) else {
+        let error = "missing `command` argument";
+        return ToolRun {
+            response: json!({ "error": error }).to_string(),
+            report: json!({ "command": null, "error": error }),
+        };
+    };
+
+    let cwd = context.cwd.clone();
+    let assessment = security::assess_command(&command, &config.security, &cwd, context.aliases());
+    let decision = assessment.decide(config.yes.value, config.security.allow_system_level.value);
+    let security = output::security_json(&assessment, Some(&decision));
+    let not_run = |confirmed: Option<bool>, error: String| ToolRun {
+        response: json!({ "error": error }).to_string(),
+        report: output::command_json(
+            &command,
+            &cwd,
+            Some(&info),
+            security.clone(),
+            confirmed,
+            None,
+            Some(&error),
+        ),
+    };
+
+    let confirmed = match decision {
+        Decision::Run => None,
+        Decision::Confirm => Some(confirm_command(&command, &info, &cwd, &assessment, config)),
+        Decision::Refuse(reason) => {
+            eprintln!("\x1b[31mRefused\x1b[0m: {reason}");
+            return not_run(None, format!("refused: {reason}"));
+        }
+    };
+    if confirmed == Some(false) {
+        if !config.json() {
+            println!("Command execution cancelled");
+        }
+        return not_run(
+            confirmed,
+            "the user declined to run this command".to_string(),
+        );
+    }
 
-    // Assuming you want the first tool call
-    if let Some(tool_call) = tool_calls.first() {
-        let args = &tool_call.fn_arguments;
+    let result = if config.json() {
+        executor::CommandExecutor::execute_quiet(
+            &command,
+            config.max_output.value,
+            config.timeout(),
+            context,
+        )
+    } else {
+        println!("Executing: {command}\n{info}");
+        executor::CommandExecutor::execute_captured(
+            &command,
+            config.max_output.value,
+            config.timeout(),
+            context,
+        )
+    };
+    let (Ok(output) | Err(output)) = result;
+    match output.status {
+        CommandStatus::TimedOut => eprintln!("\x1b[31mCommand timed out\x1b[0m"),
+        CommandStatus::Interrupted => eprintln!("\x1b[31mCommand interrupted\x1b[0m"),
+        CommandStatus::Exited => {}
+    }
+    ToolRun {
+        response: tool_result(&output, &context.cwd),
+        report: output::command_json(
+            &command,
+            &cwd,
+            Some(&info),
+            security,
+            confirmed,
+            Some(&output),
+            None,
+        ),
+    }
+}
 
-        if let (Some(Value::String(command)), Some(Value::String(info))) =
-            (args.get("command"), args.get("info"))
-        {
-            return Ok((command.clone(), info.clone()));
+/// Answers `user_prompt`, running the commands the model asks for. `files`,
+/// `@path` mentions and piped stdin are attached to the prompt. With
+/// `--output json` a single document describing the run is printed instead.
+pub async fn handle_prompt_req(
+    user_prompt: &str,
+    files: &[String],
+    config: &Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut tally = Tally::default();
+    let mut commands = Vec::new();
+    let result = ask(user_prompt, files, config, &mut tally, &mut commands).await;
+
+    if config.json() {
+        let (answer, error) = match &result {
+            Ok(answer) => (answer.as_deref(), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        output::print(&json!({
+            "prompt": user_prompt,
+            "model": config.model.value,
+            "commands": commands,
+            "answer": answer,
+            "usage": output::usage_json(&tally),
+            "error": error,
+        }));
+    } else {
+        if let Ok(Some(answer)) = &result {
+            println!("{}", markdown::render(answer));
+        }
+        if tally.requests > 0 {
+            print_usage(&tally);
         }
     }
-    Err("No tool calls returned".into())
+    result.map(|_| ())
 }
 
-pub async fn handle_prompt_req(user_prompt: &str, y: bool) -> Result<(), &str> {
-    //
-    let (command, info): (String, String) = get_command(user_prompt).await.unwrap();
-
-    let safety_check = security::validate_command(&command);
-    let requires_confirmation = if y { safety_check.is_err() } else { true };
-    if requires_confirmation {
-        const COLOR_RED: &str = "\x1b[31m";
-        const COLOR_YELLOW: &str = "\x1b[33m";
-        const COLOR_BOLD: &str = "\x1b[1m";
-        const COLOR_NC: &str = "\x1b[0m";
-
-        let reason = match safety_check {
-            Err(ValidationError::DangerousCommand) => {
-                format!("\n{COLOR_BOLD}{COLOR_RED}WARNING: This command is potentially destructive!{COLOR_NC}")
-            }
-            Err(ValidationError::ElevatedPrivileges) => {
-                format!("\n{COLOR_BOLD}{COLOR_YELLOW}WARNING: This command requires elevated privileges!{COLOR_NC}")
-            }
-            Ok(_) => String::new(),
+/// The model loop behind `handle_prompt_req`: returns the final answer, adding
+/// every call to `tally` and every command to `commands` as it goes.
+async fn ask(
+    user_prompt: &str,
+    files: &[String],
+    config: &Config,
+    tally: &mut Tally,
+    commands: &mut Vec<Value>,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let mut attachments = Attachments::new(config.max_attach.value);
+    for path in files.iter().chain(&attach::mentions(user_prompt)) {
+        attachments.add(path)?;
+    }
+    if let Some(summary) = attachments.summary() {
+        eprintln!("\x1b[33m[files] {summary}\x1b[0m");
+    }
+
+    let mut prompt = user_prompt.to_string();
+    attachments.append_to(&mut prompt);
+    if let Some(piped) = input::read_piped(config.max_input.value)? {
+        prompt.push_str(&format!("\n\n{piped}"));
+    }
+    let (client, mut chat_req) = get_ai_client(&prompt, SYSTEM_PROMPT, config).await?;
+    let mut context = ExecContext::new(config.shell.value);
+
+    for _ in 0..config.max_steps.value {
+        let chat_res = client
+            .exec_chat(&config.model.value, chat_req.clone(), None)
+            .await?;
+        tally.add(&usage::record(
+            "ask",
+            None,
+            &config.model.value,
+            &chat_res.usage,
+            config,
+        ));
+
+        let tool_calls = match chat_res.content {
+            Some(MessageContent::ToolCalls(tool_calls)) if !tool_calls.is_empty() => tool_calls,
+            _ => return Ok(chat_res.content_text_into_string()),
         };
 
-        print!(
-            "{reason}\nAre you sure you want to execute:{COLOR_RED} \"{command}\" {COLOR_NC}?\nDescription: {info}\n({COLOR_BOLD}yes{COLOR_NC}/no): "
-        );
-        // let _ = io::stdout().flush();
-        let _ = std::io::stdout().flush().map_err(|_| {
-            eprintln!("field to flush to terminal !");
-        });
-        let mut input = String::new();
-        let _ = BufReader::new(tokio::io::stdin())
-            .read_line(&mut input)
-            .await;
-
-        if !input.trim().eq_ignore_ascii_case("yes") && !input.trim().eq_ignore_ascii_case("y") {
-            println!("Command execution cancelled");
-            return Ok(());
+        chat_req = chat_req.append_message(ChatMessage::from(tool_calls.clone()));
+        for tool_call in &tool_calls {
+            let run = run_tool_call(tool_call, config, &mut context).await;
+            commands.push(run.report);
+            chat_req =
+                chat_req.append_message(ToolResponse::new(tool_call.call_id.clone(), run.response));
         }
     }
 
-    println!("Executing: {command}\n{info}");
-    let _ = executor::CommandExecutor::execute_at_once(&command);
+    Err(format!(
+        "Stopped after {} steps without a final answer",
+        config.max_steps.value
+    )
+    .into())
+}
 
-    Ok(())
+/// Prints the tokens and cost of a run on stderr, out of the way of the answer.
+fn print_usage(tally: &Tally) {
+    eprintln!("\x1b[2mUsage: {tally}\x1b[0m");
 }
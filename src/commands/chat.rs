@@ -1,9 +1,21 @@
-use crate::core::ai_client::{get_ai_client, MODEL};
-use genai::chat::printer::{print_chat_stream, PrintChatStreamOptions};
-use genai::chat::{ChatMessage, ChatRequest};
-use genai::Client;
+use crate::commands::ask;
+use crate::core::ai_client::{command_tool, get_client, ChatEvents, LlmBackend};
+use crate::core::config::{Config, Source};
+use crate::core::context;
+use crate::core::executor::ExecContext;
+use crate::core::session::{self, Session};
+use crate::core::usage::{self, Tally};
+use crate::utils::attach::{self, Attachments};
+use crate::utils::input;
+use crate::utils::line_editor::LineEditor;
+use crate::utils::markdown::{self, MarkdownRenderer};
+use futures::StreamExt;
+use genai::chat::{
+    ChatMessage, ChatOptions, ChatRequest, ChatRole, ChatStreamEvent, MessageContent, ToolResponse,
+    Usage,
+};
 use std::io::Write;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use std::path::Path;
 
 const SYSTEM_PROMPT: &str = r#"
 You are AIC, an AI assistant integrated into the 'aic' command-line interface (CLI) application, specifically designed for the 'chat' subcommand. Your name within this application is 'aic'.
@@ -29,17 +41,129 @@ Keep your responses concise and directly address the user's query. Avoid tangent
 You are aware that everything you write, including any internal thinking processes you might describe or code you provide, is visible to the user in their terminal.
 "#;
 
-pub async fn chat() -> Result<(), Box<dyn std::error::Error>> {
-    let (client, mut chat_req): (Client, ChatRequest) = get_ai_client("", SYSTEM_PROMPT).await?;
-    let print_options = PrintChatStreamOptions::from_print_events(false);
+/// Added to the system prompt of `aic chat --tools`, overriding the "no tools" part.
+const TOOLS_PROMPT: &str = r#"
+## Tools
 
+In this session you CAN run shell commands on the user's machine through the `run_commandes` tool, despite what is said above. Use it when the user asks you to inspect or change something on their system. Every command is checked and may need the user's approval; its exit status, stdout and stderr are returned to you. If a command is refused or declined, do not retry it in another form. Finish with a plain text answer.
+"#;
+
+const HELP: &str = "\
+/model [NAME]     show or switch the model
+/system [PROMPT]  show or replace the system prompt
+/clear            forget the conversation so far
+/save [FILE]      save the session now, or export it to FILE
+/load ID|FILE     continue a saved session or an exported file
+/retry            regenerate the last answer
+/undo             drop the last question and answer
+/write FILE       save the last answer to FILE
+/usage            show the tokens and cost of this chat
+/help             show this help
+exit, quit        leave the chat";
+
+/// What the loop does after a slash command.
+enum Next {
+    Prompt,
+    /// Ask the model again for the current history.
+    Respond,
+}
+
+/// Mutable state of a chat: the model can change mid-session.
+struct ChatState {
+    config: Config,
+    client: Box<dyn LlmBackend>,
+    session: Session,
+    /// Let the model run commands (`--tools`).
+    tools: bool,
+    /// Tokens and cost since the chat started.
+    usage: Tally,
+    /// Directory and environment the model's commands left behind.
+    exec: ExecContext,
+}
+
+impl ChatState {
+    fn chat_request(&self) -> ChatRequest {
+        let chat_req = self.session.chat_request();
+        if self.tools {
+            chat_req
+                .with_system(format!("{}\n{TOOLS_PROMPT}", self.session.system))
+                .with_tools(vec![command_tool()])
+        } else {
+            chat_req
+        }
+    }
+
+    /// Adds the usage of one model call to the ledger and the chat's totals.
+    fn record_usage(&mut self, usage: &Usage) {
+        let entry = usage::record(
+            "chat",
+            Some(&self.session.id),
+            &self.config.model.value,
+            usage,
+            &self.config,
+        );
+        self.usage.add(&entry);
+    }
+}
+
+pub async fn chat(
+    config: &Config,
+    resume: Option<&str>,
+    tools: bool,
+    files: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = config.clone();
+    // `--file` goes with the first message; reading it now reports errors early.
+    let mut first_files = Attachments::new(config.max_attach.value);
+    for path in files {
+        first_files.add(path)?;
+    }
+    let mut first_files = Some(first_files);
+
+    let mut session = match resume {
+        Some(id) => {
+            let session = session::find(id)?;
+            // The session keeps its model unless --model asks for another one.
+            if !matches!(config.model.source, Source::Flag) {
+                config
+                    .model
+                    .set(session.model.clone(), Source::File(session.path()?));
+            }
+            print_resumed(&session);
+            session
+        }
+        None => Session::new(&config.model.value, SYSTEM_PROMPT),
+    };
+    session.model = config.model.value.clone();
+
+    let mut state = ChatState {
+        client: get_client(&config)?,
+        exec: ExecContext::new(config.shell.value),
+        config,
+        session,
+        tools,
+        usage: Tally::default(),
+    };
+
+    // With a terminal to talk on, piped stdin is context for the first message;
+    // without one, stdin is the conversation itself.
+    let terminal = input::has_terminal();
+    let mut piped = if terminal {
+        input::read_piped(state.config.max_input.value)?
+    } else {
+        None
+    };
+    if piped.is_some() {
+        eprintln!("\x1b[33m[stdin] Piped input will be attached to your first message\x1b[0m");
+    }
+
+    let mut editor = LineEditor::new(terminal)?;
     loop {
-        print!("message : ");
-        std::io::stdout().flush()?;
-        let mut input = String::new();
-        BufReader::new(tokio::io::stdin())
-            .read_line(&mut input)
-            .await?;
+        // Reading blocks; let the runtime move other tasks off this thread.
+        let Some(input) = tokio::task::block_in_place(|| editor.read("message : "))? else {
+            println!("Exiting...");
+            break;
+        };
 
         match input.trim().to_lowercase().as_str() {
             "exit" | "quit" => {
@@ -49,14 +173,308 @@ pub async fn chat() -> Result<(), Box<dyn std::error::Error>> {
             _ => {}
         }
 
-        chat_req = chat_req.append_message(ChatMessage::user(input));
-        let chat_res = client
-            .exec_chat_stream(MODEL, chat_req.clone(), None)
+        if let Some(command) = input.trim().strip_prefix('/') {
+            match run_slash_command(&mut state, command) {
+                Ok(Next::Prompt) => continue,
+                Ok(Next::Respond) => {}
+                Err(e) => {
+                    eprintln!("{e}");
+                    continue;
+                }
+            }
+        } else {
+            let mut attachments = first_files
+                .take()
+                .unwrap_or_else(|| Attachments::new(state.config.max_attach.value));
+            for path in attach::mentions(&input) {
+                if let Err(e) = attachments.add(&path) {
+                    eprintln!("{e}");
+                }
+            }
+            if let Some(summary) = attachments.summary() {
+                eprintln!("\x1b[33m[files] {summary}\x1b[0m");
+            }
+
+            let mut message = input;
+            attachments.append_to(&mut message);
+            if let Some(piped) = piped.take() {
+                message.push_str(&format!("\n\n{piped}"));
+            }
+            state.session.push(ChatMessage::user(message));
+        }
+
+        compact_history(&mut state).await;
+        // Keep the chat alive on API errors; `/retry` sends the message again.
+        let result = if state.tools {
+            respond_with_tools(&mut state).await
+        } else {
+            respond(&mut state).await
+        };
+        if let Err(e) = result {
+            eprintln!("{e}");
+        }
+    }
+
+    if !state.session.messages.is_empty() {
+        println!(
+            "Session saved, resume it with `aic chat --resume {}`",
+            state.session.id
+        );
+    }
+    Ok(())
+}
+
+/// Summarizes older turns once the history outgrows the model's budget.
+async fn compact_history(state: &mut ChatState) {
+    const COLOR_YELLOW: &str = "\x1b[33m";
+    const COLOR_NC: &str = "\x1b[0m";
+
+    let compaction = context::compact_if_needed(
+        state.client.as_ref(),
+        &state.config.model.value,
+        &mut state.session,
+        &state.config,
+    )
+    .await;
+    match compaction {
+        Ok(Some(compaction)) => {
+            state.record_usage(&compaction.usage);
+            eprintln!(
+                "{COLOR_YELLOW}[context] Summarized {} earlier messages to stay within the budget of {} (~{} -> ~{} tokens){COLOR_NC}",
+                compaction.messages,
+                state.config.model.value,
+                compaction.tokens_before,
+                compaction.tokens_after
+            )
+        }
+        Ok(None) => {}
+        Err(e) => eprintln!("{COLOR_YELLOW}[context] {e}; sending the full history{COLOR_NC}"),
+    }
+}
+
+/// Streams the model's answer to the current history and records it.
+async fn respond(state: &mut ChatState) -> Result<(), Box<dyn std::error::Error>> {
+    let options = ChatOptions::default().with_capture_usage(true);
+    let stream = state
+        .client
+        .exec_chat_stream(
+            &state.config.model.value,
+            state.chat_request(),
+            Some(&options),
+        )
+        .await?;
+    let (assistant_answer, usage) = print_stream(stream).await?;
+    // println!();
+    if let Some(usage) = usage {
+        state.record_usage(&usage);
+    }
+    state.session.push(ChatMessage::assistant(assistant_answer));
+    state.session.save()
+}
+
+/// Prints a streamed answer as Markdown while it arrives and returns its text
+/// with the usage reported at the end of the stream.
+async fn print_stream(
+    mut stream: ChatEvents,
+) -> Result<(String, Option<Usage>), Box<dyn std::error::Error>> {
+    let mut renderer = MarkdownRenderer::new();
+    let mut answer = String::new();
+    let mut usage = None;
+    let mut stdout = std::io::stdout();
+    while let Some(event) = stream.next().await {
+        match event? {
+            ChatStreamEvent::Chunk(chunk) => {
+                answer.push_str(&chunk.content);
+                write!(stdout, "{}", renderer.push(&chunk.content))?;
+                stdout.flush()?;
+            }
+            ChatStreamEvent::End(end) => usage = end.captured_usage,
+            _ => {}
+        }
+    }
+    writeln!(stdout, "{}", renderer.finish())?;
+    Ok((answer, usage))
+}
+
+/// Lets the model run commands until it gives a text answer.
+///
+/// genai streams carry only text chunks, never tool calls, so this mode waits
+/// for complete responses instead of streaming them.
+async fn respond_with_tools(state: &mut ChatState) -> Result<(), Box<dyn std::error::Error>> {
+    for _ in 0..state.config.max_steps.value {
+        let chat_res = state
+            .client
+            .exec_chat(&state.config.model.value, state.chat_request(), None)
             .await?;
-        let assistant_answer = print_chat_stream(chat_res, Some(&print_options)).await?;
-        // println!();
-        chat_req = chat_req.append_message(ChatMessage::assistant(assistant_answer));
+        state.record_usage(&chat_res.usage);
+
+        let tool_calls = match chat_res.content {
+            Some(MessageContent::ToolCalls(tool_calls)) if !tool_calls.is_empty() => tool_calls,
+            _ => {
+                let answer = chat_res.content_text_into_string().unwrap_or_default();
+                println!("{}", markdown::render(&answer));
+                state.session.push(ChatMessage::assistant(answer));
+                return state.session.save();
+            }
+        };
+
+        state.session.push(ChatMessage::from(tool_calls.clone()));
+        for tool_call in &tool_calls {
+            let run = ask::run_tool_call(tool_call, &state.config, &mut state.exec).await;
+            state
+                .session
+                .push(ToolResponse::new(tool_call.call_id.clone(), run.response).into());
+        }
+        state.session.save()?;
+    }
+
+    Err(format!(
+        "Stopped after {} steps without a final answer",
+        state.config.max_steps.value
+    )
+    .into())
+}
+
+fn run_slash_command(
+    state: &mut ChatState,
+    command: &str,
+) -> Result<Next, Box<dyn std::error::Error>> {
+    let (name, arg) = match command.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, arg.trim()),
+        None => (command, ""),
+    };
+    let session = &mut state.session;
+
+    match name {
+        "help" => println!("{HELP}"),
+        "usage" => println!("{}", state.usage),
+        "model" if arg.is_empty() => println!("model: {}", state.config.model.value),
+        "model" => {
+            let mut config = state.config.clone();
+            config.model.set(arg.to_string(), Source::Flag);
+            state.client = get_client(&config)?;
+            state.config = config;
+            session.model = arg.to_string();
+            println!("Switched to {arg}");
+        }
+        "system" if arg.is_empty() => println!("{}", session.system.trim()),
+        "system" => {
+            session.system = arg.to_string();
+            println!("System prompt replaced");
+        }
+        "clear" => {
+            session.messages.clear();
+            println!("History cleared");
+        }
+        "save" if arg.is_empty() => {
+            session.save()?;
+            println!("Saved session {}", session.id);
+            return Ok(Next::Prompt);
+        }
+        "save" => {
+            session.write_to(Path::new(arg))?;
+            println!("Exported session to {arg}");
+            return Ok(Next::Prompt);
+        }
+        "load" if arg.is_empty() => return Err("Usage: /load ID|FILE".into()),
+        "load" => {
+            let file = Path::new(arg);
+            let (loaded, path) = if file.is_file() {
+                (Session::read_from(file)?, std::path::absolute(file)?)
+            } else {
+                let loaded = session::find(arg)?;
+                let path = loaded.path()?;
+                (loaded, path)
+            };
+            let mut config = state.config.clone();
+            config.model.set(loaded.model.clone(), Source::File(path));
+            state.client = get_client(&config)?;
+            state.config = config;
+            print_resumed(&loaded);
+            state.session = loaded;
+            return Ok(Next::Prompt);
+        }
+        "retry" => {
+            // Drop everything after the last question, tool calls included.
+            let last_user = session
+                .messages
+                .iter()
+                .rposition(|m| matches!(m.role, ChatRole::User))
+                .ok_or("Nothing to retry")?;
+            session.messages.truncate(last_user + 1);
+            return Ok(Next::Respond);
+        }
+        "undo" => {
+            let last_user = session
+                .messages
+                .iter()
+                .rposition(|m| matches!(m.role, ChatRole::User))
+                .ok_or("Nothing to undo")?;
+            session.messages.truncate(last_user);
+            println!("Dropped the last exchange");
+        }
+        "write" if arg.is_empty() => return Err("Usage: /write FILE".into()),
+        "write" => {
+            let answer = last_answer(session).ok_or("No answer to write yet")?;
+            std::fs::write(arg, answer).map_err(|e| format!("Failed to write {arg}: {e}"))?;
+            println!("Wrote the last answer to {arg}");
+            return Ok(Next::Prompt);
+        }
+        _ => return Err(format!("Unknown command /{name}, see /help").into()),
+    }
+
+    // A titled session is on disk already and must reflect the change.
+    if !session.title.is_empty() {
+        session.save()?;
+    }
+    Ok(Next::Prompt)
+}
+
+fn last_answer(session: &Session) -> Option<&str> {
+    session
+        .messages
+        .iter()
+        .rev()
+        .find(|m| matches!(m.role, ChatRole::Assistant))
+        .and_then(session::message_text)
+}
+
+/// Shows where a resumed conversation left off.
+fn print_resumed(session: &Session) {
+    println!(
+        "Resuming \"{}\" ({}, {} messages, {})",
+        session.title,
+        session.id,
+        session.messages.len(),
+        session::age(session.updated_at)
+    );
+    if let Some(answer) = last_answer(session) {
+        println!("\n{answer}\n");
+    }
+}
+
+/// Prints the stored sessions, most recent first.
+pub fn list_sessions() -> Result<(), Box<dyn std::error::Error>> {
+    let sessions = session::list()?;
+    if sessions.is_empty() {
+        println!("No saved chat sessions");
+        return Ok(());
+    }
+    let width = sessions.iter().map(|s| s.id.len()).max().unwrap_or(0);
+    for session in sessions {
+        println!(
+            "{:<width$}  {:>8}  {:>3} msgs  {}",
+            session.id,
+            session::age(session.updated_at),
+            session.messages.len(),
+            session.title
+        );
     }
+    Ok(())
+}
 
+pub fn delete_session(id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let session = session::delete(id)?;
+    println!("Deleted session {} \"{}\"", session.id, session.title);
     Ok(())
 }
@@ -0,0 +1,36 @@
+use crate::core::config::{self, Config, PROJECT_FILE, PROJECT_POLICY_FILE};
+
+/// Prints the effective configuration with the origin of every value.
+pub fn show(config: &Config) {
+    let entries = config.entries();
+    let width = entries
+        .iter()
+        .map(|(key, _, _)| key.len())
+        .max()
+        .unwrap_or(0);
+    for (key, value, source) in entries {
+        println!("{key:<width$} = {value}  # {source}");
+    }
+
+    println!();
+    match config::user_config_path() {
+        Some(path) => println!("user config:    {}", path.display()),
+        None => println!("user config:    unavailable ($HOME is not set)"),
+    }
+    match config::find_project_config() {
+        Some(path) => println!("project config: {}", path.display()),
+        None => {
+            println!("project config: no {PROJECT_FILE} in the current directory or its parents")
+        }
+    }
+    match config::user_policy_path() {
+        Some(path) => println!("user policy:    {}", path.display()),
+        None => println!("user policy:    unavailable ($HOME is not set)"),
+    }
+    match config::find_project_file(PROJECT_POLICY_FILE) {
+        Some(path) => println!("project policy: {}", path.display()),
+        None => println!(
+            "project policy: no {PROJECT_POLICY_FILE} in the current directory or its parents"
+        ),
+    }
+}
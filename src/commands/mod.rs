@@ -1,3 +1,7 @@
 pub mod ask;
 pub mod chat;
+pub mod config;
+pub mod output;
+pub mod policy;
 pub mod run;
+pub mod usage;
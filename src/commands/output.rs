@@ -0,0 +1,101 @@
+use crate::core::executor::CommandOutput;
+use crate::core::usage::Tally;
+use crate::utils::security::{Assessment, Decision};
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// The security classification of a command. `decision` is left out when the
+/// caller does not act on it.
+pub fn security_json(assessment: &Assessment, decision: Option<&Decision>) -> Value {
+    let findings: Vec<Value> = assessment
+        .findings
+        .iter()
+        .map(|finding| {
+            json!({
+                "segment": finding.segment,
+                "tier": finding.tier.number(),
+                "reason": finding.reason,
+            })
+        })
+        .collect();
+    let rules: Vec<Value> = assessment
+        .matched
+        .iter()
+        .map(|matched| json!({ "segment": matched.segment, "rule": matched.rule.to_string() }))
+        .collect();
+    let mut security = json!({
+        "tier": assessment.tier.number(),
+        "label": assessment.tier.label(),
+        "findings": findings,
+        "rules": rules,
+        "notes": assessment.notes,
+    });
+    if let Some(decision) = decision {
+        let (action, reason) = match decision {
+            Decision::Run => ("run", None),
+            Decision::Confirm => ("confirm", None),
+            Decision::Refuse(reason) => ("refuse", Some(reason)),
+        };
+        security["decision"] = json!({ "action": action, "reason": reason });
+    }
+    security
+}
+
+/// One command as reported by `--output json`, with the directory it was to
+/// run in. `confirmed` is `None` when the user was not asked; `output` is
+/// `None` when the command did not run, and `error` then says why.
+pub fn command_json(
+    command: &str,
+    cwd: &Path,
+    info: Option<&str>,
+    security: Value,
+    confirmed: Option<bool>,
+    output: Option<&CommandOutput>,
+    error: Option<&str>,
+) -> Value {
+    let mut report = json!({
+        "command": command,
+        "cwd": cwd.display().to_string(),
+        "info": info,
+        "security": security,
+        "confirmed": confirmed,
+        "executed": output.is_some(),
+        "status": null,
+        "exit_code": null,
+        "signal": null,
+        "stdout": null,
+        "stderr": null,
+        "duration_ms": null,
+        "error": error,
+    });
+    if let Some(output) = output {
+        report["status"] = json!(output.status.as_str());
+        report["exit_code"] = json!(output.status_code);
+        report["signal"] = json!(output.signal);
+        report["stdout"] = json!(output.stdout);
+        report["stderr"] = json!(output.stderr);
+        report["duration_ms"] = json!(output.duration.as_millis() as u64);
+        report["error"] = json!(error.or(output.output_str.as_deref()));
+    }
+    report
+}
+
+/// Token counts and cost; `cost` is null when no call had a known price.
+pub fn usage_json(tally: &Tally) -> Value {
+    let priced = tally.requests == 0 || tally.unpriced < tally.requests;
+    json!({
+        "requests": tally.requests,
+        "prompt_tokens": tally.prompt_tokens,
+        "completion_tokens": tally.completion_tokens,
+        "cost": priced.then_some(tally.cost),
+        "unpriced": tally.unpriced,
+    })
+}
+
+/// Writes the document to stdout, the only thing printed there in JSON mode.
+pub fn print(document: &Value) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(document).unwrap_or_default()
+    );
+}
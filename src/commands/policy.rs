@@ -0,0 +1,58 @@
+use crate::core::config::{self, Config, PROJECT_POLICY_FILE};
+use crate::utils::security::{self, Decision};
+use std::collections::BTreeMap;
+
+/// Shows which policy rules match `command` and what `aic ask` would do with it.
+pub fn check(command: &str, config: &Config) {
+    let policy = &config.security.policy;
+    if policy.files.is_empty() {
+        println!("policy files: none");
+    } else {
+        println!("policy files:");
+        for file in &policy.files {
+            println!("  {}", file.display());
+        }
+    }
+    match config::user_policy_path() {
+        Some(path) if !policy.files.contains(&path) => {
+            println!("  (no user policy at {})", path.display())
+        }
+        _ => {}
+    }
+    if config::find_project_file(PROJECT_POLICY_FILE).is_none() {
+        println!("  (no {PROJECT_POLICY_FILE} in the current directory or its parents)");
+    }
+    println!();
+
+    let assessment = security::assess_command(
+        command,
+        &config.security,
+        &std::env::current_dir().unwrap_or_default(),
+        &BTreeMap::new(),
+    );
+    if assessment.matched.is_empty() {
+        println!("rules: none matched, built-in analysis applies");
+    } else {
+        println!("rules:");
+        for matched in &assessment.matched {
+            println!("  {}: {}", matched.segment, matched.rule);
+        }
+    }
+    if !assessment.findings.is_empty() {
+        println!("findings:");
+        for finding in &assessment.findings {
+            println!("  - {finding}");
+        }
+    }
+    for note in &assessment.notes {
+        println!("  * {note}");
+    }
+    println!("risk: {}", assessment.tier);
+
+    let decision = assessment.decide(config.yes.value, config.security.allow_system_level.value);
+    match decision {
+        Decision::Run => println!("decision: run without asking"),
+        Decision::Confirm => println!("decision: ask for confirmation"),
+        Decision::Refuse(reason) => println!("decision: refuse ({reason})"),
+    }
+}
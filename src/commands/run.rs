@@ -1,24 +1,47 @@
-use crate::core::executor::CommandExecutor;
-use std::char;
-use std::io::{self, Read, Write};
+use crate::commands::output;
+use crate::core::config::Config;
+use crate::core::executor::{CommandExecutor, CommandStatus, ExecContext};
+use crate::utils::input;
+use crate::utils::security::{self, Assessment, Decision};
+use std::io;
 
-pub fn handle_command_execution(command_parts: &str, y: bool) {
-    if !y {
-        print!("Are you sure you want to execute this :\" {command_parts} \" ");
-        io::stdout().flush().unwrap();
-        let ch = Read::bytes(io::stdin()).next().unwrap().unwrap() as char;
-
-        if ch == 'N' || ch == 'n' {
-            println!("Exiting...");
-            return;
-        }
+pub fn handle_command_execution(command_parts: &str, config: &Config) {
+    let context = ExecContext::default();
+    let assessment = security::assess_command(
+        command_parts,
+        &config.security,
+        &context.cwd,
+        context.aliases(),
+    );
+    let decision = assessment.decide(config.yes.value, config.security.allow_system_level.value);
+    if config.json() {
+        return report_command_execution(command_parts, config, context, &assessment, decision);
+    }
+    if let Decision::Refuse(reason) = &decision {
+        eprintln!("\x1b[31mRefused\x1b[0m: {reason}");
+        return;
     }
-    let _ = match CommandExecutor::execute_at_once(command_parts) {
+    if needs_confirmation(&decision, config) && !confirm(command_parts, &mut io::stdout()) {
+        println!("Exiting...");
+        return;
+    }
+    let _ = match CommandExecutor::execute_at_once(command_parts, config.timeout()) {
         Ok(o) => {
             // println!("stdout: \n{}", o.output_str);
             println!("status code: {}", o.status_code);
             o.output_str
         }
+        Err(err) if err.status == CommandStatus::TimedOut => {
+            eprintln!(
+                "\x1b[31mCommand timed out after {}s\x1b[0m",
+                err.duration.as_secs()
+            );
+            err.output_str
+        }
+        Err(err) if err.status == CommandStatus::Interrupted => {
+            eprintln!("\x1b[31mCommand interrupted\x1b[0m");
+            err.output_str
+        }
         Err(err) => {
             eprintln!(
                 "\x1b[31mCommand failed with exit code: {}\x1b[0m",
@@ -28,3 +51,68 @@ pub fn handle_command_execution(command_parts: &str, y: bool) {
         }
     };
 }
+
+/// `run --output json`: the same checks, with the confirmation asked on
+/// stderr, then the command's captured output and classification as one JSON
+/// document.
+fn report_command_execution(
+    command: &str,
+    config: &Config,
+    mut context: ExecContext,
+    assessment: &Assessment,
+    decision: Decision,
+) {
+    let security = output::security_json(assessment, Some(&decision));
+    let not_run = |confirmed: Option<bool>, error: &str| {
+        output::print(&output::command_json(
+            command,
+            &context.cwd,
+            None,
+            security.clone(),
+            confirmed,
+            None,
+            Some(error),
+        ))
+    };
+
+    if let Decision::Refuse(reason) = &decision {
+        eprintln!("\x1b[31mRefused\x1b[0m: {reason}");
+        return not_run(None, &format!("refused: {reason}"));
+    }
+    let confirmed =
+        needs_confirmation(&decision, config).then(|| confirm(command, &mut io::stderr()));
+    if confirmed == Some(false) {
+        return not_run(confirmed, "the user declined to run this command");
+    }
+
+    let cwd = context.cwd.clone();
+    let (Ok(result) | Err(result)) = CommandExecutor::execute_quiet(
+        command,
+        config.max_output.value,
+        config.timeout(),
+        &mut context,
+    );
+    output::print(&output::command_json(
+        command,
+        &cwd,
+        None,
+        security,
+        confirmed,
+        Some(&result),
+        None,
+    ));
+}
+
+/// `run` asks before every command unless `-y` is given, and despite `-y`
+/// when the risk tier or a `confirm` rule demands it.
+fn needs_confirmation(decision: &Decision, config: &Config) -> bool {
+    !config.yes.value || *decision == Decision::Confirm
+}
+
+/// Asks on the terminal, or on `fallback` and stdin without one. Only `y` or
+/// `yes` runs the command; an empty answer or end of input does not.
+fn confirm(command: &str, fallback: &mut dyn io::Write) -> bool {
+    let prompt = format!("Are you sure you want to execute this :\" {command} \" ");
+    let answer = input::prompt_line(&prompt, fallback).unwrap_or_default();
+    answer.trim().eq_ignore_ascii_case("yes") || answer.trim().eq_ignore_ascii_case("y")
+}
@@ -0,0 +1,212 @@
+use crate::core::session;
+use crate::core::usage::{self, Entry, Tally};
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+
+/// How `aic usage` groups the ledger.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum GroupBy {
+    Model,
+    Day,
+    Command,
+}
+
+/// Prints the recorded usage since `since` (e.g. `7d`), grouped by `group_by`.
+pub fn show(since: Option<&str>, group_by: GroupBy) -> Result<(), Box<dyn std::error::Error>> {
+    let start = match since {
+        Some(since) => session::now().saturating_sub(parse_duration(since)?),
+        None => 0,
+    };
+    let entries = usage::read_ledger(start)?;
+    if entries.is_empty() {
+        println!("No usage recorded in {}", usage::ledger_path()?.display());
+        return Ok(());
+    }
+    print!("{}", report(&entries, group_by));
+    Ok(())
+}
+
+/// The table `aic usage` prints: one row per group, then the total.
+fn report(entries: &[Entry], group_by: GroupBy) -> String {
+    let mut groups: BTreeMap<String, Tally> = BTreeMap::new();
+    let mut total = Tally::default();
+    for entry in entries {
+        groups
+            .entry(group_key(entry, group_by))
+            .or_default()
+            .add(entry);
+        total.add(entry);
+    }
+
+    let label = match group_by {
+        GroupBy::Model => "MODEL",
+        GroupBy::Day => "DAY",
+        GroupBy::Command => "COMMAND",
+    };
+    let width = groups
+        .keys()
+        .map(|key| key.len())
+        .chain([label.len(), "TOTAL".len()])
+        .max()
+        .unwrap_or_default();
+    let mut out = String::new();
+    out.push_str(&format!(
+        "{label:<width$}  {:>8}  {:>12}  {:>12}  {:>10}\n",
+        "REQUESTS", "PROMPT", "COMPLETION", "COST"
+    ));
+    for (key, tally) in groups.iter().chain([(&"TOTAL".to_string(), &total)]) {
+        out.push_str(&format!(
+            "{key:<width$}  {:>8}  {:>12}  {:>12}  {:>10}\n",
+            tally.requests,
+            tally.prompt_tokens,
+            tally.completion_tokens,
+            tally.cost_text()
+        ));
+    }
+    if total.unpriced > 0 {
+        out.push_str(&format!(
+            "\nNot in the cost: {} with no known price; set one under [prices] in the config.\n",
+            plural(total.unpriced, "request")
+        ));
+    }
+    out
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{count} {noun}")
+    } else {
+        format!("{count} {noun}s")
+    }
+}
+
+fn group_key(entry: &Entry, group_by: GroupBy) -> String {
+    match group_by {
+        GroupBy::Model => entry.model.clone(),
+        GroupBy::Day => date(entry.timestamp),
+        GroupBy::Command => entry.command.clone(),
+    }
+}
+
+/// Seconds in a duration such as `90m`, `12h`, `7d` or `2w`.
+fn parse_duration(text: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid duration {text:?}, expected e.g. 30m, 12h, 7d or 2w");
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (count, unit) = text.split_at(split);
+    let count: u64 = count.parse().map_err(|_| invalid())?;
+    let unit = match unit {
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => return Err(invalid()),
+    };
+    count
+        .checked_mul(unit)
+        .ok_or_else(|| format!("Duration {text:?} is too long"))
+}
+
+/// UTC date of a Unix timestamp as `YYYY-MM-DD`.
+fn date(timestamp: u64) -> String {
+    // Howard Hinnant's days-to-civil algorithm.
+    let days = (timestamp / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: u64, command: &str, model: &str, tokens: u64, cost: Option<f64>) -> Entry {
+        Entry {
+            timestamp,
+            command: command.to_string(),
+            session: None,
+            model: model.to_string(),
+            prompt_tokens: tokens,
+            completion_tokens: tokens / 10,
+            cost,
+        }
+    }
+
+    #[test]
+    fn durations_are_parsed_in_seconds() {
+        assert_eq!(parse_duration("90m"), Ok(5400));
+        assert_eq!(parse_duration("12h"), Ok(43_200));
+        assert_eq!(parse_duration("7d"), Ok(604_800));
+        assert_eq!(parse_duration("2w"), Ok(1_209_600));
+        assert_eq!(parse_duration("0d"), Ok(0));
+        for invalid in ["", "7", "d", "-1d", "1.5h", "7 d", "7days", "1y"] {
+            let error = parse_duration(invalid).unwrap_err();
+            assert!(error.starts_with("Invalid duration"), "{invalid}: {error}");
+        }
+    }
+
+    #[test]
+    fn huge_durations_are_an_error() {
+        let error = parse_duration("99999999999999999w").unwrap_err();
+        assert!(error.contains("too long"), "{error}");
+        assert!(parse_duration("999999999999999999999d").is_err());
+    }
+
+    #[test]
+    fn dates_are_utc_days() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(951_782_400), "2000-02-29");
+        assert_eq!(date(1_767_225_599), "2025-12-31");
+    }
+
+    #[test]
+    fn the_report_sums_each_group_and_the_total() {
+        let entries = [
+            entry(86_400, "ask", "gpt-4o", 1000, Some(0.5)),
+            entry(86_401, "chat", "gpt-4o", 3000, Some(0.25)),
+            entry(2 * 86_400, "chat", "llama3", 200, None),
+        ];
+        assert_eq!(
+            report(&entries, GroupBy::Model),
+            "\
+MODEL   REQUESTS        PROMPT    COMPLETION        COST
+gpt-4o         2          4000           400     $0.7500
+llama3         1           200            20           ?
+TOTAL          3          4200           420     $0.7500
+
+Not in the cost: 1 request with no known price; set one under [prices] in the config.
+"
+        );
+        let by_day = report(&entries, GroupBy::Day);
+        assert!(
+            by_day.contains("\n1970-01-02         2          4000"),
+            "{by_day}"
+        );
+        assert!(
+            by_day.contains("\n1970-01-03         1           200"),
+            "{by_day}"
+        );
+        let by_command = report(&entries, GroupBy::Command);
+        assert!(by_command.starts_with("COMMAND  "), "{by_command}");
+        assert!(
+            by_command.contains("\nchat            2          3200"),
+            "{by_command}"
+        );
+    }
+
+    #[test]
+    fn the_report_has_no_note_when_everything_is_priced() {
+        let entries = [entry(0, "ask", "gpt-4o", 10, Some(0.0))];
+        let report = report(&entries, GroupBy::Command);
+        assert!(!report.contains("Not in the cost"), "{report}");
+        assert_eq!(report.lines().count(), 3);
+    }
+}
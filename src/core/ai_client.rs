@@ -1,17 +1,96 @@
-use genai::resolver::{AuthData, AuthResolver};
+use crate::core::config::{Config, EndpointConfig};
+use crate::core::environment::Environment;
+use crate::core::mock::MockBackend;
+use crate::core::record::{Recorder, Replayer};
+use futures::future::LocalBoxFuture;
+use futures::stream::LocalBoxStream;
+use futures::StreamExt;
+use genai::resolver::{AuthData, AuthResolver, Endpoint, ServiceTargetResolver};
 use genai::{
-    chat::{ChatMessage, ChatRequest, Tool},
-    Client, ModelIden,
+    adapter::AdapterKind,
+    chat::{ChatMessage, ChatOptions, ChatRequest, ChatStreamEvent, MessageContent, Tool, Usage},
+    Client, ModelIden, ServiceTarget,
 };
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::BTreeMap;
+use std::path::Path;
 
 pub const MODEL: &str = "gemini-2.0-flash";
 
-fn get_chat_req(
-    user_prompt: &str,
-    system_prompt: &str,
-) -> Result<ChatRequest, Box<dyn std::error::Error>> {
-    let execute_cmd_tool = Tool::new("run_commandes")
+/// Model names starting with this select the scripted [`MockBackend`], e.g.
+/// `mock:tests/fixtures/answer.toml`.
+pub const MOCK_PREFIX: &str = "mock:";
+
+/// A complete answer of the model.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatReply {
+    pub content: Option<MessageContent>,
+    pub usage: Usage,
+}
+
+impl ChatReply {
+    pub fn content_text_into_string(self) -> Option<String> {
+        match self.content {
+            Some(MessageContent::Text(text)) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+/// Events of a streamed answer.
+pub type ChatEvents = LocalBoxStream<'static, Result<ChatStreamEvent, Box<dyn std::error::Error>>>;
+
+/// Where chat requests go: a real provider through genai's [`Client`], or the
+/// scripted [`MockBackend`] for tests.
+pub trait LlmBackend {
+    fn exec_chat<'a>(
+        &'a self,
+        model: &'a str,
+        chat_req: ChatRequest,
+        options: Option<&'a ChatOptions>,
+    ) -> LocalBoxFuture<'a, Result<ChatReply, Box<dyn std::error::Error>>>;
+
+    fn exec_chat_stream<'a>(
+        &'a self,
+        model: &'a str,
+        chat_req: ChatRequest,
+        options: Option<&'a ChatOptions>,
+    ) -> LocalBoxFuture<'a, Result<ChatEvents, Box<dyn std::error::Error>>>;
+}
+
+impl LlmBackend for Client {
+    fn exec_chat<'a>(
+        &'a self,
+        model: &'a str,
+        chat_req: ChatRequest,
+        options: Option<&'a ChatOptions>,
+    ) -> LocalBoxFuture<'a, Result<ChatReply, Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            let chat_res = Client::exec_chat(self, model, chat_req, options).await?;
+            Ok(ChatReply {
+                content: chat_res.content,
+                usage: chat_res.usage,
+            })
+        })
+    }
+
+    fn exec_chat_stream<'a>(
+        &'a self,
+        model: &'a str,
+        chat_req: ChatRequest,
+        options: Option<&'a ChatOptions>,
+    ) -> LocalBoxFuture<'a, Result<ChatEvents, Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            let chat_res = Client::exec_chat_stream(self, model, chat_req, options).await?;
+            Ok(chat_res.stream.map(|event| Ok(event?)).boxed_local())
+        })
+    }
+}
+
+/// The `run_commandes` tool through which the model asks to run a shell command.
+pub fn command_tool() -> Tool {
+    Tool::new("run_commandes")
         .with_description("run the commande in the terminal like this in UNIX <sh -c 'commande'> in WINDOWS <cmd /C 'command'>")
         .with_schema(json!(
             {
@@ -28,39 +107,257 @@ fn get_chat_req(
                     },
                 "required" : ["command", "info"],
             }
-        ));
+        ))
+}
+
+/// The first request of `ask`. With `environment`, the prompt starts with a
+/// summary of the OS, project and tools.
+fn get_chat_req(
+    user_prompt: &str,
+    system_prompt: &str,
+    environment: bool,
+) -> Result<ChatRequest, Box<dyn std::error::Error>> {
+    let prompt = if environment {
+        format!("{}\n\n{user_prompt}", Environment::collect().summary())
+    } else {
+        user_prompt.to_string()
+    };
     let chat_req = ChatRequest::default()
         .with_system(system_prompt)
-        .with_tools(vec![execute_cmd_tool])
-        .append_message(ChatMessage::user(user_prompt));
+        .with_tools(vec![command_tool()])
+        .append_message(ChatMessage::user(prompt));
     Ok(chat_req)
 }
 
-pub async fn get_ai_client(
-    user_prompt: &str,
-    system_prompt: &str,
-) -> Result<(Client, ChatRequest), Box<dyn std::error::Error>> {
-    //
+/// Env var consulted for every provider when its own key is not set.
+pub const FALLBACK_KEY_ENV: &str = "MODEL_API_KEY";
+
+/// Looks up the API key for `adapter_kind`: the env var configured under
+/// `[api_keys]`, else the provider's conventional one (`OPENAI_API_KEY`,
+/// `GEMINI_API_KEY`, ...), else `MODEL_API_KEY`. Providers that need no key
+/// (Ollama) resolve to `None`.
+pub fn resolve_api_key(
+    adapter_kind: AdapterKind,
+    key_env_names: &BTreeMap<String, String>,
+) -> Result<Option<String>, String> {
+    key_from(adapter_kind, key_env_names, &|name| {
+        std::env::var(name).ok()
+    })
+}
+
+/// [`resolve_api_key`] with the environment read through `env`.
+fn key_from(
+    adapter_kind: AdapterKind,
+    key_env_names: &BTreeMap<String, String>,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<Option<String>, String> {
+    let provider_env = match key_env_names.get(adapter_kind.as_lower_str()) {
+        Some(env_name) => env_name.as_str(),
+        None => match adapter_kind.default_key_env_name() {
+            Some(env_name) => env_name,
+            None => return Ok(None),
+        },
+    };
+
+    [provider_env, FALLBACK_KEY_ENV]
+        .iter()
+        .find_map(|name| env(name).filter(|key| !key.is_empty()))
+        .map(Some)
+        .ok_or_else(|| {
+            format!(
+                "No API key for {}: set {provider_env} (or {FALLBACK_KEY_ENV})",
+                adapter_kind.as_str()
+            )
+        })
+}
+
+/// Env var naming a directory where every exchange with the model is written.
+pub const RECORD_ENV: &str = "AIC_RECORD";
+
+/// Env var naming a recorded directory whose responses are served instead of
+/// the model's.
+pub const REPLAY_ENV: &str = "AIC_REPLAY";
+
+/// Builds the backend for `config.model`: a recording being replayed, the mock,
+/// or a provider client. Recording wraps whichever it is.
+pub fn get_client(config: &Config) -> Result<Box<dyn LlmBackend>, Box<dyn std::error::Error>> {
+    let backend = match std::env::var_os(REPLAY_ENV).filter(|dir| !dir.is_empty()) {
+        Some(dir) => Box::new(Replayer::open(Path::new(&dir))?),
+        None => get_model_client(config)?,
+    };
+    match std::env::var_os(RECORD_ENV).filter(|dir| !dir.is_empty()) {
+        Some(dir) => Ok(Box::new(Recorder::create(Path::new(&dir), backend)?)),
+        None => Ok(backend),
+    }
+}
+
+/// Builds a client for `config.model`, failing early when its API key is missing.
+fn get_model_client(config: &Config) -> Result<Box<dyn LlmBackend>, Box<dyn std::error::Error>> {
+    if let Some(fixture) = config.model.value.strip_prefix(MOCK_PREFIX) {
+        return Ok(Box::new(MockBackend::load(Path::new(fixture))?));
+    }
+    if let Some(base_url) = &config.endpoint.base_url.value {
+        return Ok(Box::new(get_endpoint_client(
+            base_url,
+            &config.endpoint,
+            !config.json(),
+        )?));
+    }
+
+    // provider name -> env var holding its key, as set under `[api_keys]`
+    let key_env_names: BTreeMap<String, String> = config
+        .api_keys
+        .iter()
+        .map(|(provider, env_name)| (provider.clone(), env_name.value.clone()))
+        .collect();
+
+    // Fail before the first request with a message naming the missing variable.
+    let adapter_kind = AdapterKind::from_model(&config.model.value)?;
+    resolve_api_key(adapter_kind, &key_env_names)?;
+    // The banner would corrupt a JSON document on stdout.
+    let banner = !config.json();
+
     let auth_resolver = AuthResolver::from_resolver_fn(
-        |model_iden: ModelIden| -> Result<Option<AuthData>, genai::resolver::Error> {
+        move |model_iden: ModelIden| -> Result<Option<AuthData>, genai::resolver::Error> {
             let ModelIden {
                 adapter_kind,
                 model_name,
             } = model_iden;
-            println!("\n>>{adapter_kind} (model: {model_name})<<");
-
-            // This will cause it to fail if any model is not an GOOGLE_API_KEY
-            let key = std::env::var("MODEL_API_KEY").map_err(|_| {
-                genai::resolver::Error::ApiKeyEnvNotFound {
-                    env_name: "MODEL_API_KEY".to_string(),
-                }
-            })?;
-            Ok(Some(AuthData::from_single(key)))
+            if banner {
+                println!("\n>>{adapter_kind} (model: {model_name})<<");
+            }
+
+            let key = resolve_api_key(adapter_kind, &key_env_names)
+                .map_err(genai::resolver::Error::Custom)?;
+            Ok(key.map(AuthData::from_single))
         },
     );
 
     // -- Build the new client with this adapter_config
-    let client = Client::builder().with_auth_resolver(auth_resolver).build();
-    let chat_req = get_chat_req(user_prompt, system_prompt)?;
+    Ok(Box::new(
+        Client::builder().with_auth_resolver(auth_resolver).build(),
+    ))
+}
+
+/// Builds a client sending every request to `base_url`, whatever the model
+/// name. No API key is required unless `endpoint.api_key_env` names one.
+fn get_endpoint_client(
+    base_url: &str,
+    endpoint: &EndpointConfig,
+    banner: bool,
+) -> Result<Client, Box<dyn std::error::Error>> {
+    let adapter_kind = match endpoint.adapter.value.as_str() {
+        "openai" => AdapterKind::OpenAI,
+        "ollama" => AdapterKind::Ollama,
+        other => {
+            return Err(format!(
+                "Unknown endpoint.adapter {other:?}: expected \"openai\" or \"ollama\""
+            )
+            .into())
+        }
+    };
+    // The adapters append `chat/completions` to the base URL.
+    let base_url = format!("{}/", base_url.trim_end_matches('/'));
+    let key = match &endpoint.api_key_env.value {
+        Some(env_name) => std::env::var(env_name)
+            .ok()
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| format!("No API key for {base_url}: set {env_name}"))?,
+        // Servers without authentication ignore the header.
+        None => String::new(),
+    };
+
+    let target_resolver = ServiceTargetResolver::from_resolver_fn(
+        move |target: ServiceTarget| -> Result<ServiceTarget, genai::resolver::Error> {
+            let model_name = target.model.model_name;
+            if banner {
+                println!("\n>>{adapter_kind} (model: {model_name}) at {base_url}<<");
+            }
+            Ok(ServiceTarget {
+                endpoint: Endpoint::from_owned(base_url.clone()),
+                auth: AuthData::from_single(key.clone()),
+                model: ModelIden::new(adapter_kind, model_name),
+            })
+        },
+    );
+    Ok(Client::builder()
+        .with_service_target_resolver(target_resolver)
+        .build())
+}
+
+pub async fn get_ai_client(
+    user_prompt: &str,
+    system_prompt: &str,
+    config: &Config,
+) -> Result<(Box<dyn LlmBackend>, ChatRequest), Box<dyn std::error::Error>> {
+    let client = get_client(config)?;
+    let chat_req = get_chat_req(user_prompt, system_prompt, config.env_context.value)?;
     Ok((client, chat_req))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(
+        adapter_kind: AdapterKind,
+        configured: &[(&str, &str)],
+        vars: &[(&str, &str)],
+    ) -> Result<Option<String>, String> {
+        let names = configured
+            .iter()
+            .map(|(provider, name)| (provider.to_string(), name.to_string()))
+            .collect();
+        let vars: BTreeMap<&str, &str> = vars.iter().copied().collect();
+        key_from(adapter_kind, &names, &|name| {
+            vars.get(name).map(|value| value.to_string())
+        })
+    }
+
+    #[test]
+    fn keys_fall_back_from_the_configured_variable_to_the_provider_one() {
+        let vars = [
+            ("WORK_OPENAI_KEY", "work"),
+            ("OPENAI_API_KEY", "provider"),
+            ("MODEL_API_KEY", "fallback"),
+        ];
+        let configured = [("openai", "WORK_OPENAI_KEY")];
+        assert_eq!(
+            key(AdapterKind::OpenAI, &configured, &vars)
+                .unwrap()
+                .as_deref(),
+            Some("work")
+        );
+        assert_eq!(
+            key(AdapterKind::OpenAI, &[], &vars).unwrap().as_deref(),
+            Some("provider")
+        );
+        // A configured name replaces the provider's, and empty counts as unset.
+        let vars = [("WORK_OPENAI_KEY", ""), vars[1], vars[2]];
+        assert_eq!(
+            key(AdapterKind::OpenAI, &configured, &vars)
+                .unwrap()
+                .as_deref(),
+            Some("fallback")
+        );
+        assert_eq!(
+            key(AdapterKind::Gemini, &[], &[("GEMINI_API_KEY", "gemini")])
+                .unwrap()
+                .as_deref(),
+            Some("gemini")
+        );
+        assert_eq!(key(AdapterKind::Ollama, &[], &[]), Ok(None));
+    }
+
+    #[test]
+    fn a_missing_key_names_the_variable_to_set() {
+        assert_eq!(
+            key(AdapterKind::OpenAI, &[("openai", "WORK_OPENAI_KEY")], &[]),
+            Err("No API key for OpenAI: set WORK_OPENAI_KEY (or MODEL_API_KEY)".to_string())
+        );
+        assert_eq!(
+            key(AdapterKind::Anthropic, &[], &[("OPENAI_API_KEY", "other")]),
+            Err("No API key for Anthropic: set ANTHROPIC_API_KEY (or MODEL_API_KEY)".to_string())
+        );
+    }
+}
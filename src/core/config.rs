@@ -0,0 +1,711 @@
+use crate::core::ai_client::MODEL;
+use crate::core::executor::{ShellMode, DEFAULT_CAPTURE_LIMIT};
+use crate::core::usage::Price;
+use crate::utils::attach::DEFAULT_ATTACH_LIMIT;
+use crate::utils::input::DEFAULT_INPUT_LIMIT;
+use crate::utils::policy::Policy;
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Names of the user config and policy files in the user config directory.
+const USER_FILE: &str = "config.toml";
+const USER_POLICY_FILE: &str = "policy.toml";
+
+/// Name of the per-project config file, looked up from the cwd towards `/`.
+pub const PROJECT_FILE: &str = ".aic.toml";
+
+/// Name of the per-project policy file, looked up like [`PROJECT_FILE`].
+pub const PROJECT_POLICY_FILE: &str = ".aic-policy.toml";
+
+pub const DEFAULT_MAX_STEPS: usize = 10;
+
+/// Protocol assumed for `endpoint.base_url`.
+pub const DEFAULT_ENDPOINT_ADAPTER: &str = "openai";
+
+/// Chat messages kept verbatim when older ones are summarized.
+pub const DEFAULT_KEEP_RECENT: usize = 6;
+
+/// Where the effective value of a setting came from.
+#[derive(Debug, Clone)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    Env(&'static str),
+    Flag,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File(path) => write!(f, "{}", path.display()),
+            Source::Env(name) => write!(f, "env {name}"),
+            Source::Flag => write!(f, "command-line flag"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Setting<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+impl<T> Setting<T> {
+    fn default(value: T) -> Self {
+        Setting {
+            value,
+            source: Source::Default,
+        }
+    }
+
+    pub fn set(&mut self, value: T, source: Source) {
+        self.value = value;
+        self.source = source;
+    }
+
+    fn merge(&mut self, value: Option<T>, source: &Source) {
+        if let Some(value) = value {
+            self.set(value, source.clone());
+        }
+    }
+}
+
+/// Shape of `config.toml` / `.aic.toml`. Every key is optional so a file only
+/// needs to mention what it overrides.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    model: Option<String>,
+    yes: Option<bool>,
+    timeout: Option<u64>,
+    max_steps: Option<usize>,
+    max_output: Option<usize>,
+    max_input: Option<usize>,
+    max_attach: Option<usize>,
+    env_context: Option<bool>,
+    shell: Option<ShellMode>,
+    #[serde(default)]
+    api_keys: BTreeMap<String, String>,
+    #[serde(default)]
+    security: SecurityFile,
+    #[serde(default)]
+    context: ContextFile,
+    #[serde(default)]
+    prices: BTreeMap<String, Price>,
+    #[serde(default)]
+    endpoint: EndpointFile,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SecurityFile {
+    dangerous_patterns: Option<Vec<String>>,
+    block_elevated: Option<bool>,
+    allow_system_level: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ContextFile {
+    keep_recent: Option<usize>,
+    #[serde(default)]
+    budgets: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EndpointFile {
+    base_url: Option<String>,
+    adapter: Option<String>,
+    api_key_env: Option<String>,
+}
+
+/// How `ask` and `run` report: text for people, or one JSON document on stdout
+/// for scripts (`--output`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+/// Effective configuration: built-in defaults, then the user file, the project
+/// file, `AIC_*` environment variables and finally command-line flags.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub model: Setting<String>,
+    /// Run commands that pass validation without asking, like `-y`.
+    pub yes: Setting<bool>,
+    /// Seconds before a running command is terminated.
+    pub timeout: Setting<Option<u64>>,
+    pub max_steps: Setting<usize>,
+    pub max_output: Setting<usize>,
+    /// Bytes of piped stdin attached to the prompt.
+    pub max_input: Setting<usize>,
+    /// Bytes of `--file` and `@path` content attached to one message.
+    pub max_attach: Setting<usize>,
+    /// Whether `ask` prompts start with a summary of the environment.
+    pub env_context: Setting<bool>,
+    /// Run agent commands one-shot or in a persistent shell.
+    pub shell: Setting<ShellMode>,
+    pub output: Setting<OutputFormat>,
+    /// Provider name (`openai`, `gemini`, ...) to the env var holding its API key.
+    pub api_keys: BTreeMap<String, Setting<String>>,
+    pub security: SecurityConfig,
+    pub context: ContextConfig,
+    /// Model name (or prefix) to its price, overriding the built-in list prices.
+    pub prices: BTreeMap<String, Setting<Price>>,
+    pub endpoint: EndpointConfig,
+}
+
+#[derive(Debug, Clone)]
+pub struct SecurityConfig {
+    /// Extra substrings that mark a command as dangerous.
+    pub dangerous_patterns: Setting<Vec<String>>,
+    /// Refuse commands needing `sudo`/`doas` instead of asking for confirmation.
+    pub block_elevated: Setting<bool>,
+    /// Offer tier 4 (system-level) commands for confirmation instead of refusing them.
+    pub allow_system_level: Setting<bool>,
+    /// Allow/confirm/deny rules from the user and project policy files.
+    pub policy: Policy,
+}
+
+/// How much chat history is sent to the model.
+#[derive(Debug, Clone)]
+pub struct ContextConfig {
+    /// Messages kept verbatim when older ones are summarized.
+    pub keep_recent: Setting<usize>,
+    /// Model name (or prefix) to the estimated tokens the history may use.
+    pub budgets: BTreeMap<String, Setting<usize>>,
+}
+
+/// A self-hosted server (Ollama, llama.cpp, vLLM, ...) used instead of the
+/// provider inferred from the model name.
+#[derive(Debug, Clone)]
+pub struct EndpointConfig {
+    /// e.g. `http://localhost:11434/v1/`; unset to use the providers.
+    pub base_url: Setting<Option<String>>,
+    /// Protocol the server speaks: `openai` or `ollama`.
+    pub adapter: Setting<String>,
+    /// Env var holding the server's API key; none is sent when unset.
+    pub api_key_env: Setting<Option<String>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            model: Setting::default(MODEL.to_string()),
+            yes: Setting::default(false),
+            timeout: Setting::default(None),
+            max_steps: Setting::default(DEFAULT_MAX_STEPS),
+            max_output: Setting::default(DEFAULT_CAPTURE_LIMIT),
+            max_input: Setting::default(DEFAULT_INPUT_LIMIT),
+            max_attach: Setting::default(DEFAULT_ATTACH_LIMIT),
+            env_context: Setting::default(true),
+            shell: Setting::default(ShellMode::OneShot),
+            output: Setting::default(OutputFormat::Text),
+            api_keys: BTreeMap::new(),
+            security: SecurityConfig {
+                dangerous_patterns: Setting::default(Vec::new()),
+                block_elevated: Setting::default(false),
+                allow_system_level: Setting::default(false),
+                policy: Policy::default(),
+            },
+            context: ContextConfig {
+                keep_recent: Setting::default(DEFAULT_KEEP_RECENT),
+                budgets: BTreeMap::new(),
+            },
+            prices: BTreeMap::new(),
+            endpoint: EndpointConfig {
+                base_url: Setting::default(None),
+                adapter: Setting::default(DEFAULT_ENDPOINT_ADAPTER.to_string()),
+                api_key_env: Setting::default(None),
+            },
+        }
+    }
+}
+
+impl Config {
+    /// Loads every layer except command-line flags, which the caller applies with
+    /// [`Setting::set`].
+    pub fn load() -> Result<Config, Box<dyn std::error::Error>> {
+        let cwd = std::env::current_dir().unwrap_or_default();
+        Config::load_from(&process_env, &cwd)
+    }
+
+    /// [`Config::load`] with the environment read through `env` and the project
+    /// files looked up from `cwd`.
+    fn load_from(env: Env, cwd: &Path) -> Result<Config, Box<dyn std::error::Error>> {
+        let mut config = Config::default();
+        let user_dir = user_config_dir(env);
+
+        if let Some(path) = user_dir
+            .as_ref()
+            .map(|dir| dir.join(USER_FILE))
+            .filter(|p| p.is_file())
+        {
+            config.merge_file(&path)?;
+        }
+        if let Some(path) = find_above(cwd, PROJECT_FILE) {
+            config.merge_project_file(&path)?;
+        }
+        config.merge_env(env)?;
+
+        if let Some(path) = user_dir
+            .map(|dir| dir.join(USER_POLICY_FILE))
+            .filter(|p| p.is_file())
+        {
+            config.security.policy.load_file(&path)?;
+        }
+        if let Some(path) = find_above(cwd, PROJECT_POLICY_FILE) {
+            config.security.policy.load_project_file(&path)?;
+        }
+
+        Ok(config)
+    }
+
+    fn merge_file(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        self.apply_file(read_config(path)?, &Source::File(path.to_path_buf()));
+        Ok(())
+    }
+
+    /// Merges a project file, which comes with the repository and so may only
+    /// make the checks stricter. Its `dangerous_patterns` add to the user's.
+    fn merge_project_file(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = read_config(path)?;
+        if let Some(key) = loosened(&file) {
+            return Err(format!(
+                "{}: {key} can only be set in the user config",
+                path.display()
+            )
+            .into());
+        }
+        if let Some(patterns) = file.security.dangerous_patterns.take() {
+            let mut all = self.security.dangerous_patterns.value.clone();
+            all.extend(patterns);
+            file.security.dangerous_patterns = Some(all);
+        }
+        self.apply_file(file, &Source::File(path.to_path_buf()));
+        Ok(())
+    }
+
+    fn apply_file(&mut self, file: ConfigFile, source: &Source) {
+        self.model.merge(file.model, source);
+        self.yes.merge(file.yes, source);
+        self.timeout.merge(file.timeout.map(Some), source);
+        self.max_steps.merge(file.max_steps, source);
+        self.max_output.merge(file.max_output, source);
+        self.max_input.merge(file.max_input, source);
+        self.max_attach.merge(file.max_attach, source);
+        self.env_context.merge(file.env_context, source);
+        self.shell.merge(file.shell, source);
+        for (provider, env_name) in file.api_keys {
+            self.api_keys.insert(
+                provider.to_lowercase(),
+                Setting {
+                    value: env_name,
+                    source: source.clone(),
+                },
+            );
+        }
+        self.security
+            .dangerous_patterns
+            .merge(file.security.dangerous_patterns, source);
+        self.security
+            .block_elevated
+            .merge(file.security.block_elevated, source);
+        self.security
+            .allow_system_level
+            .merge(file.security.allow_system_level, source);
+        self.context
+            .keep_recent
+            .merge(file.context.keep_recent, source);
+        for (model, budget) in file.context.budgets {
+            self.context.budgets.insert(
+                model,
+                Setting {
+                    value: budget,
+                    source: source.clone(),
+                },
+            );
+        }
+        self.endpoint
+            .base_url
+            .merge(file.endpoint.base_url.map(Some), source);
+        self.endpoint.adapter.merge(file.endpoint.adapter, source);
+        self.endpoint
+            .api_key_env
+            .merge(file.endpoint.api_key_env.map(Some), source);
+        for (model, price) in file.prices {
+            self.prices.insert(
+                model,
+                Setting {
+                    value: price,
+                    source: source.clone(),
+                },
+            );
+        }
+    }
+
+    fn merge_env(&mut self, env: Env) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(model) = env_value(env, "AIC_MODEL") {
+            self.model.set(model, Source::Env("AIC_MODEL"));
+        }
+        if let Some(base_url) = env_value(env, "AIC_BASE_URL") {
+            self.endpoint
+                .base_url
+                .set(Some(base_url), Source::Env("AIC_BASE_URL"));
+        }
+        if let Some(yes) = env_parse::<bool>(env, "AIC_YES")? {
+            self.yes.set(yes, Source::Env("AIC_YES"));
+        }
+        if let Some(timeout) = env_parse::<u64>(env, "AIC_TIMEOUT")? {
+            self.timeout.set(Some(timeout), Source::Env("AIC_TIMEOUT"));
+        }
+        if let Some(max_steps) = env_parse::<usize>(env, "AIC_MAX_STEPS")? {
+            self.max_steps.set(max_steps, Source::Env("AIC_MAX_STEPS"));
+        }
+        if let Some(max_output) = env_parse::<usize>(env, "AIC_MAX_OUTPUT")? {
+            self.max_output
+                .set(max_output, Source::Env("AIC_MAX_OUTPUT"));
+        }
+        if let Some(max_input) = env_parse::<usize>(env, "AIC_MAX_INPUT")? {
+            self.max_input.set(max_input, Source::Env("AIC_MAX_INPUT"));
+        }
+        if let Some(max_attach) = env_parse::<usize>(env, "AIC_MAX_ATTACH")? {
+            self.max_attach
+                .set(max_attach, Source::Env("AIC_MAX_ATTACH"));
+        }
+        if let Some(env_context) = env_parse::<bool>(env, "AIC_ENV_CONTEXT")? {
+            self.env_context
+                .set(env_context, Source::Env("AIC_ENV_CONTEXT"));
+        }
+        if let Some(shell) = env_parse::<ShellMode>(env, "AIC_SHELL")? {
+            self.shell.set(shell, Source::Env("AIC_SHELL"));
+        }
+        Ok(())
+    }
+
+    /// Whether `ask` and `run` print a JSON document instead of text.
+    pub fn json(&self) -> bool {
+        self.output.value == OutputFormat::Json
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout.value.map(Duration::from_secs)
+    }
+
+    /// Every setting as `(key, value, source)`, in display order.
+    pub fn entries(&self) -> Vec<(String, String, &Source)> {
+        let mut entries = vec![
+            entry("model", &self.model),
+            entry("yes", &self.yes),
+            entry("timeout", &self.timeout),
+            entry("max_steps", &self.max_steps),
+            entry("max_output", &self.max_output),
+            entry("max_input", &self.max_input),
+            entry("max_attach", &self.max_attach),
+            entry("env_context", &self.env_context),
+            entry("shell", &self.shell),
+        ];
+        for (provider, env_name) in &self.api_keys {
+            entries.push(entry(&format!("api_keys.{provider}"), env_name));
+        }
+        entries.push(entry(
+            "security.dangerous_patterns",
+            &self.security.dangerous_patterns,
+        ));
+        entries.push(entry(
+            "security.block_elevated",
+            &self.security.block_elevated,
+        ));
+        entries.push(entry(
+            "security.allow_system_level",
+            &self.security.allow_system_level,
+        ));
+        entries.push(entry("context.keep_recent", &self.context.keep_recent));
+        for (model, budget) in &self.context.budgets {
+            entries.push(entry(&format!("context.budgets.{model:?}"), budget));
+        }
+        for (model, price) in &self.prices {
+            entries.push(entry(&format!("prices.{model:?}"), price));
+        }
+        entries.push(entry("endpoint.base_url", &self.endpoint.base_url));
+        entries.push(entry("endpoint.adapter", &self.endpoint.adapter));
+        entries.push(entry("endpoint.api_key_env", &self.endpoint.api_key_env));
+        entries
+    }
+}
+
+fn entry<'a, T: TomlValue>(key: &str, setting: &'a Setting<T>) -> (String, String, &'a Source) {
+    (key.to_string(), setting.value.to_toml(), &setting.source)
+}
+
+/// Renders a setting the way it would be written in the config file.
+trait TomlValue {
+    fn to_toml(&self) -> String;
+}
+
+impl TomlValue for String {
+    fn to_toml(&self) -> String {
+        format!("{self:?}")
+    }
+}
+
+impl TomlValue for bool {
+    fn to_toml(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl TomlValue for usize {
+    fn to_toml(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl TomlValue for Option<u64> {
+    fn to_toml(&self) -> String {
+        match self {
+            Some(v) => v.to_string(),
+            None => "unset".to_string(),
+        }
+    }
+}
+
+impl TomlValue for Option<String> {
+    fn to_toml(&self) -> String {
+        match self {
+            Some(v) => format!("{v:?}"),
+            None => "unset".to_string(),
+        }
+    }
+}
+
+impl TomlValue for Price {
+    fn to_toml(&self) -> String {
+        format!("{{ input = {}, output = {} }}", self.input, self.output)
+    }
+}
+
+impl TomlValue for ShellMode {
+    fn to_toml(&self) -> String {
+        format!("{:?}", self.as_str())
+    }
+}
+
+impl TomlValue for Vec<String> {
+    fn to_toml(&self) -> String {
+        format!("{self:?}")
+    }
+}
+
+fn read_config(path: &Path) -> Result<ConfigFile, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    toml::from_str(&text).map_err(|e| format!("Invalid config {}: {e}", path.display()))
+}
+
+/// The first setting in `file` that loosens the checks or decides where API
+/// keys are sent.
+fn loosened(file: &ConfigFile) -> Option<&'static str> {
+    if file.yes == Some(true) {
+        Some("yes = true")
+    } else if file.security.allow_system_level == Some(true) {
+        Some("security.allow_system_level = true")
+    } else if file.security.block_elevated == Some(false) {
+        Some("security.block_elevated = false")
+    } else if !file.api_keys.is_empty() {
+        Some("[api_keys]")
+    } else if file.endpoint.base_url.is_some()
+        || file.endpoint.adapter.is_some()
+        || file.endpoint.api_key_env.is_some()
+    {
+        Some("[endpoint]")
+    } else {
+        None
+    }
+}
+
+/// Looks up an environment variable; tests pass a map instead of the process
+/// environment.
+type Env<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+fn process_env(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+fn env_value(env: Env, name: &str) -> Option<String> {
+    env(name).filter(|v| !v.is_empty())
+}
+
+fn env_parse<T: std::str::FromStr>(
+    env: Env,
+    name: &str,
+) -> Result<Option<T>, Box<dyn std::error::Error>> {
+    match env_value(env, name) {
+        Some(raw) => raw
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("Invalid value for {name}: {raw:?}").into()),
+        None => Ok(None),
+    }
+}
+
+/// `$XDG_CONFIG_HOME/aic`, falling back to `~/.config/aic`.
+fn user_config_dir(env: Env) -> Option<PathBuf> {
+    let base = env_value(env, "XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env_value(env, "HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(base.join("aic"))
+}
+
+/// `$XDG_DATA_HOME/aic`, falling back to `~/.local/share/aic`.
+pub fn user_data_dir() -> Option<PathBuf> {
+    let env: Env = &process_env;
+    let base = env_value(env, "XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env_value(env, "HOME").map(|home| Path::new(&home).join(".local/share")))?;
+    Some(base.join("aic"))
+}
+
+/// `$XDG_CONFIG_HOME/aic/config.toml`, falling back to `~/.config/aic/config.toml`.
+pub fn user_config_path() -> Option<PathBuf> {
+    user_config_dir(&process_env).map(|dir| dir.join(USER_FILE))
+}
+
+/// `policy.toml` next to the user config file.
+pub fn user_policy_path() -> Option<PathBuf> {
+    user_config_dir(&process_env).map(|dir| dir.join(USER_POLICY_FILE))
+}
+
+/// Closest `.aic.toml` in the current directory or one of its parents.
+pub fn find_project_config() -> Option<PathBuf> {
+    find_project_file(PROJECT_FILE)
+}
+
+/// Closest file called `name` in the current directory or one of its parents.
+pub fn find_project_file(name: &str) -> Option<PathBuf> {
+    find_above(&std::env::current_dir().ok()?, name)
+}
+
+/// Closest file called `name` in `dir` or one of its parents.
+fn find_above(dir: &Path, name: &str) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory standing in for HOME.
+    fn home(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("aic-config-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(path: &Path, text: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, text).unwrap();
+    }
+
+    fn load(vars: &[(&str, &str)], cwd: &Path) -> Result<Config, Box<dyn std::error::Error>> {
+        let vars: BTreeMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        Config::load_from(&|name| vars.get(name).cloned(), cwd)
+    }
+
+    #[test]
+    fn layers_apply_in_order_and_keep_their_source() {
+        let home = home("layers");
+        let user = home.join(".config/aic/config.toml");
+        let project = home.join("project/.aic.toml");
+        let cwd = home.join("project/src");
+        std::fs::create_dir_all(&cwd).unwrap();
+        write(
+            &user,
+            "model = \"user-model\"\ntimeout = 10\nmax_steps = 3\nmax_output = 100\n",
+        );
+        write(&project, "model = \"project-model\"\nmax_steps = 4\n");
+        let home_var = home.to_str().unwrap();
+
+        let mut config = load(&[("HOME", home_var), ("AIC_MAX_STEPS", "5")], &cwd).unwrap();
+        assert_eq!(config.model.value, "project-model");
+        assert_eq!(
+            config.model.source.to_string(),
+            project.display().to_string()
+        );
+        assert_eq!(config.timeout.value, Some(10));
+        assert_eq!(
+            config.timeout.source.to_string(),
+            user.display().to_string()
+        );
+        assert_eq!(config.max_output.value, 100);
+        assert_eq!(config.max_steps.value, 5);
+        assert_eq!(config.max_steps.source.to_string(), "env AIC_MAX_STEPS");
+        assert_eq!(config.max_input.value, DEFAULT_INPUT_LIMIT);
+        assert_eq!(config.max_input.source.to_string(), "default");
+
+        // Flags are applied last by the caller.
+        config.max_steps.set(6, Source::Flag);
+        assert_eq!(config.max_steps.value, 6);
+        assert_eq!(config.max_steps.source.to_string(), "command-line flag");
+
+        // XDG_CONFIG_HOME takes the place of ~/.config, policy file included.
+        let xdg = home.join("xdg");
+        write(&xdg.join("aic/config.toml"), "model = \"xdg-model\"\n");
+        write(
+            &xdg.join("aic/policy.toml"),
+            "[[deny]]\nprogram = \"terraform\"\n",
+        );
+        let config = load(
+            &[
+                ("HOME", home_var),
+                ("XDG_CONFIG_HOME", xdg.to_str().unwrap()),
+            ],
+            &home,
+        )
+        .unwrap();
+        assert_eq!(config.model.value, "xdg-model");
+        assert_eq!(config.security.policy.files, [xdg.join("aic/policy.toml")]);
+
+        std::fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn bad_environment_values_are_reported() {
+        let home = home("env");
+        let home_var = home.to_str().unwrap();
+
+        for (name, value) in [
+            ("AIC_TIMEOUT", "soon"),
+            ("AIC_YES", "maybe"),
+            ("AIC_SHELL", "zsh"),
+        ] {
+            let Err(error) = load(&[("HOME", home_var), (name, value)], &home) else {
+                panic!("{name}={value} was accepted");
+            };
+            assert_eq!(
+                error.to_string(),
+                format!("Invalid value for {name}: {value:?}")
+            );
+        }
+
+        // An empty variable counts as unset.
+        let config = load(&[("HOME", home_var), ("AIC_TIMEOUT", "")], &home).unwrap();
+        assert_eq!(config.timeout.value, None);
+        assert_eq!(config.timeout.source.to_string(), "default");
+
+        std::fs::remove_dir_all(&home).unwrap();
+    }
+}
@@ -0,0 +1,385 @@
+use crate::core::ai_client::LlmBackend;
+use crate::core::config::Config;
+use crate::core::session::Session;
+use genai::chat::{ChatMessage, ChatRequest, ChatRole, ContentPart, MessageContent, Usage};
+
+/// Rough ratio for English text and code; providers do not expose their
+/// tokenizers, and an estimate is enough to stay clear of the limit.
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Role markers and separators each message costs on top of its content.
+const MESSAGE_OVERHEAD: usize = 4;
+
+/// Tokens the history may use for models without a configured budget, by
+/// name prefix. About three quarters of each context window, leaving room
+/// for the answer.
+const DEFAULT_BUDGETS: &[(&str, usize)] = &[
+    ("gemini", 750_000),
+    ("claude", 150_000),
+    ("gpt-4.1", 750_000),
+    ("gpt-4o", 96_000),
+    ("gpt-4-turbo", 96_000),
+    ("o1", 150_000),
+    ("o3", 150_000),
+    ("o4", 150_000),
+    ("gpt-3.5", 12_000),
+    ("deepseek", 48_000),
+    ("grok", 96_000),
+    ("command", 96_000),
+];
+
+/// For models none of the prefixes above match, e.g. local Ollama models.
+const FALLBACK_BUDGET: usize = 6_000;
+
+const SUMMARY_PROMPT: &str = "You compress chat transcripts. Summarize the conversation you are given so that it can replace it as context for the rest of the chat: keep facts, decisions, names, file paths, commands and their results, open questions and the user's preferences. Leave out greetings and repetition. Write plain prose or short bullet points, no preamble.";
+
+/// Marks the synthetic message holding the summary of earlier turns.
+pub const SUMMARY_HEADER: &str = "[Summary of the earlier conversation]";
+
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+pub fn estimate_message(message: &ChatMessage) -> usize {
+    let content = match &message.content {
+        MessageContent::Text(text) => estimate_tokens(text),
+        MessageContent::Parts(parts) => parts
+            .iter()
+            .map(|part| match part {
+                ContentPart::Text(text) => estimate_tokens(text),
+                // Providers bill images separately; count a typical tile.
+                ContentPart::Image { .. } => 1_000,
+            })
+            .sum(),
+        MessageContent::ToolCalls(calls) => calls
+            .iter()
+            .map(|call| {
+                estimate_tokens(&call.fn_name) + estimate_tokens(&call.fn_arguments.to_string())
+            })
+            .sum(),
+        MessageContent::ToolResponses(responses) => responses
+            .iter()
+            .map(|response| estimate_tokens(&response.content))
+            .sum(),
+    };
+    MESSAGE_OVERHEAD + content
+}
+
+/// Estimated tokens of the system prompt and every message of `session`.
+pub fn estimate_session(session: &Session) -> usize {
+    estimate_tokens(&session.system) + session.messages.iter().map(estimate_message).sum::<usize>()
+}
+
+/// Token budget for `model`: the `[context.budgets]` entry with the longest
+/// matching prefix, else a built-in default for its family.
+pub fn budget(model: &str, config: &Config) -> usize {
+    let configured = config
+        .context
+        .budgets
+        .iter()
+        .filter(|(name, _)| model.starts_with(name.as_str()))
+        .max_by_key(|(name, _)| name.len())
+        .map(|(_, budget)| budget.value);
+    configured
+        .or_else(|| {
+            DEFAULT_BUDGETS
+                .iter()
+                .find(|(prefix, _)| model.starts_with(prefix))
+                .map(|(_, budget)| *budget)
+        })
+        .unwrap_or(FALLBACK_BUDGET)
+}
+
+/// What a compaction did, for the notice shown to the user.
+pub struct Compaction {
+    pub messages: usize,
+    pub tokens_before: usize,
+    pub tokens_after: usize,
+    /// Tokens the summarizing call used.
+    pub usage: Usage,
+}
+
+/// Replaces the older messages of `session` with a summary when the history
+/// no longer fits the budget of `model`. The system prompt and the last
+/// `keep_recent` messages are kept verbatim. If summarizing fails the history
+/// is left untouched.
+pub async fn compact_if_needed(
+    client: &dyn LlmBackend,
+    model: &str,
+    session: &mut Session,
+    config: &Config,
+) -> Result<Option<Compaction>, Box<dyn std::error::Error>> {
+    let tokens_before = estimate_session(session);
+    if tokens_before <= budget(model, config) {
+        return Ok(None);
+    }
+    let Some(split) = split_point(&session.messages, config.context.keep_recent.value) else {
+        return Ok(None);
+    };
+
+    let (summary, usage) = summarize(client, model, &session.messages[..split])
+        .await
+        .map_err(|e| format!("Failed to summarize the earlier conversation: {e}"))?;
+    let summary = [
+        ChatMessage::user(format!("{SUMMARY_HEADER}\n{summary}")),
+        ChatMessage::assistant("Understood, I will continue from this summary."),
+    ];
+    session.messages.splice(..split, summary);
+
+    Ok(Some(Compaction {
+        messages: split,
+        tokens_before,
+        tokens_after: estimate_session(session),
+        usage,
+    }))
+}
+
+/// Index of the first message to keep: the user message that starts the turn
+/// containing the last `keep_recent` messages, so tool calls stay with their
+/// results. `None` when there is nothing older to compact.
+fn split_point(messages: &[ChatMessage], keep_recent: usize) -> Option<usize> {
+    let last = messages.len().checked_sub(1)?;
+    let start = messages.len().saturating_sub(keep_recent.max(1)).min(last);
+    let split = messages[..=start]
+        .iter()
+        .rposition(|m| matches!(m.role, ChatRole::User))?;
+    (split > 0).then_some(split)
+}
+
+async fn summarize(
+    client: &dyn LlmBackend,
+    model: &str,
+    messages: &[ChatMessage],
+) -> Result<(String, Usage), Box<dyn std::error::Error>> {
+    let chat_req = ChatRequest::default()
+        .with_system(SUMMARY_PROMPT)
+        .append_message(ChatMessage::user(transcript(messages)));
+    let chat_res = client.exec_chat(model, chat_req, None).await?;
+    let usage = chat_res.usage.clone();
+    let summary = chat_res
+        .content_text_into_string()
+        .filter(|summary| !summary.trim().is_empty())
+        .ok_or("the model returned an empty summary")?;
+    Ok((summary, usage))
+}
+
+/// Messages as plain text for the summarizer.
+fn transcript(messages: &[ChatMessage]) -> String {
+    let mut text = String::new();
+    for message in messages {
+        let speaker = match message.role {
+            ChatRole::System => "System",
+            ChatRole::User => "User",
+            ChatRole::Assistant => "Assistant",
+            ChatRole::Tool => "Tool",
+        };
+        let content = match &message.content {
+            MessageContent::Text(content) => content.clone(),
+            MessageContent::Parts(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    ContentPart::Text(text) => Some(text.as_str()),
+                    ContentPart::Image { .. } => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            MessageContent::ToolCalls(calls) => calls
+                .iter()
+                .map(|call| format!("calls {} {}", call.fn_name, call.fn_arguments))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            MessageContent::ToolResponses(responses) => responses
+                .iter()
+                .map(|response| response.content.clone())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+        text.push_str(&format!("{speaker}: {content}\n\n"));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ai_client::{ChatEvents, ChatReply};
+    use crate::core::config::{Setting, Source};
+    use futures::future::LocalBoxFuture;
+    use genai::chat::ChatOptions;
+    use std::cell::RefCell;
+
+    const MODEL: &str = "test-model";
+
+    /// Answers every request with `summary`, or fails without one.
+    struct Summarizer {
+        summary: Option<&'static str>,
+        requests: RefCell<Vec<ChatRequest>>,
+    }
+
+    impl Summarizer {
+        fn new(summary: Option<&'static str>) -> Self {
+            Summarizer {
+                summary,
+                requests: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl LlmBackend for Summarizer {
+        fn exec_chat<'a>(
+            &'a self,
+            _model: &'a str,
+            chat_req: ChatRequest,
+            _options: Option<&'a ChatOptions>,
+        ) -> LocalBoxFuture<'a, Result<ChatReply, Box<dyn std::error::Error>>> {
+            self.requests.borrow_mut().push(chat_req);
+            Box::pin(async move {
+                let summary = self.summary.ok_or("unavailable")?;
+                Ok(ChatReply {
+                    content: Some(MessageContent::Text(summary.to_string())),
+                    usage: Usage::default(),
+                })
+            })
+        }
+
+        fn exec_chat_stream<'a>(
+            &'a self,
+            _model: &'a str,
+            _chat_req: ChatRequest,
+            _options: Option<&'a ChatOptions>,
+        ) -> LocalBoxFuture<'a, Result<ChatEvents, Box<dyn std::error::Error>>> {
+            unreachable!("summaries are not streamed")
+        }
+    }
+
+    /// A session of `turns` question and answer pairs.
+    fn session(turns: usize) -> Session {
+        let mut session = Session::new(MODEL, "You are terse.");
+        for turn in 1..=turns {
+            session.push(ChatMessage::user(format!("question {turn}")));
+            session.push(ChatMessage::assistant(format!("answer {turn}")));
+        }
+        session
+    }
+
+    fn config(budget: usize, keep_recent: usize) -> Config {
+        let mut config = Config::default();
+        config.context.keep_recent.set(keep_recent, Source::Flag);
+        config.context.budgets.insert(
+            MODEL.to_string(),
+            Setting {
+                value: budget,
+                source: Source::Flag,
+            },
+        );
+        config
+    }
+
+    fn text(message: &ChatMessage) -> &str {
+        match &message.content {
+            MessageContent::Text(text) => text,
+            other => panic!("not text: {other:?}"),
+        }
+    }
+
+    fn compact(
+        client: &Summarizer,
+        session: &mut Session,
+        config: &Config,
+    ) -> Result<Option<Compaction>, Box<dyn std::error::Error>> {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(compact_if_needed(client, MODEL, session, config))
+    }
+
+    #[test]
+    fn history_within_the_budget_is_left_alone() {
+        let client = Summarizer::new(Some("summary"));
+        let mut session = session(5);
+        let tokens = estimate_session(&session);
+
+        let compaction = compact(&client, &mut session, &config(tokens, 2)).unwrap();
+        assert!(compaction.is_none());
+        assert_eq!(session.messages.len(), 10);
+        assert!(client.requests.borrow().is_empty());
+    }
+
+    #[test]
+    fn older_turns_are_summarized_past_the_budget() {
+        let client = Summarizer::new(Some("they asked five questions"));
+        let mut session = session(5);
+        let tokens = estimate_session(&session);
+
+        let compaction = compact(&client, &mut session, &config(tokens - 1, 2))
+            .unwrap()
+            .expect("compacted");
+        assert_eq!(compaction.messages, 8);
+        assert_eq!(compaction.tokens_before, tokens);
+        assert_eq!(compaction.tokens_after, estimate_session(&session));
+
+        assert_eq!(session.system, "You are terse.");
+        let texts: Vec<&str> = session.messages.iter().map(text).collect();
+        assert_eq!(texts.len(), 4);
+        assert_eq!(
+            texts[0],
+            format!("{SUMMARY_HEADER}\nthey asked five questions")
+        );
+        assert_eq!(texts[2..], ["question 5", "answer 5"]);
+
+        // Only the dropped turns are sent to be summarized.
+        let requests = client.requests.borrow();
+        let transcript = text(&requests[0].messages[0]);
+        assert!(transcript.contains("User: question 1"), "{transcript}");
+        assert!(transcript.contains("Assistant: answer 4"), "{transcript}");
+        assert!(!transcript.contains("question 5"), "{transcript}");
+    }
+
+    #[test]
+    fn a_failed_summary_keeps_the_history() {
+        let client = Summarizer::new(None);
+        let mut session = session(5);
+
+        let Err(error) = compact(&client, &mut session, &config(1, 2)) else {
+            panic!("the summary should fail");
+        };
+        assert!(error.to_string().contains("unavailable"), "{error}");
+        assert_eq!(session.messages.len(), 10);
+    }
+
+    #[test]
+    fn budgets_come_from_the_config_then_the_model_family() {
+        let config = config(1234, 2);
+        assert_eq!(budget(MODEL, &config), 1234);
+        assert_eq!(budget("test-model-large", &config), 1234);
+        assert_eq!(budget("claude-sonnet-4", &config), 150_000);
+        assert_eq!(budget("llama3", &config), FALLBACK_BUDGET);
+    }
+
+    #[test]
+    fn estimates_count_the_system_prompt_and_each_message() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        let session = session(1);
+        assert_eq!(
+            estimate_session(&session),
+            estimate_tokens("You are terse.")
+                + 2 * MESSAGE_OVERHEAD
+                + estimate_tokens("question 1")
+                + estimate_tokens("answer 1")
+        );
+    }
+
+    #[test]
+    fn the_split_keeps_whole_turns() {
+        let messages = session(3).messages;
+        // The last three messages start mid-turn, so the whole turn is kept.
+        assert_eq!(split_point(&messages, 3), Some(2));
+        assert_eq!(split_point(&messages, 2), Some(4));
+        assert_eq!(split_point(&messages, 0), Some(4));
+        // Nothing older than the turns to keep.
+        assert_eq!(split_point(&messages, 6), None);
+        assert_eq!(split_point(&session(1).messages, 1), None);
+        assert_eq!(split_point(&[], 2), None);
+    }
+}
@@ -0,0 +1,283 @@
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+/// How long a probe may take before it is killed and ignored, so a hung tool
+/// does not hold up the prompt.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Manifest files by project stack, with the tools worth reporting for it.
+const STACKS: &[(&str, &[&str], &[&str])] = &[
+    ("Rust", &["Cargo.toml"], &["cargo", "rustc"]),
+    ("Node.js", &["package.json"], &["node", "npm"]),
+    (
+        "Python",
+        &["pyproject.toml", "requirements.txt", "setup.py", "Pipfile"],
+        &["python3", "pip3"],
+    ),
+    ("Go", &["go.mod"], &["go"]),
+    ("PHP", &["composer.json"], &["php", "composer"]),
+    ("Ruby", &["Gemfile"], &["ruby", "bundle"]),
+    ("Java", &["pom.xml"], &["java", "mvn"]),
+    (
+        "Java/Kotlin",
+        &["build.gradle", "build.gradle.kts"],
+        &["java", "gradle"],
+    ),
+    ("C/C++", &["CMakeLists.txt"], &["cmake"]),
+    ("Make", &["Makefile"], &["make"]),
+    (
+        "Docker",
+        &["Dockerfile", "docker-compose.yml", "compose.yaml"],
+        &["docker"],
+    ),
+];
+
+/// What aic knows about where it runs, sent ahead of `ask` prompts so the model
+/// does not have to discover it with commands.
+#[derive(Debug)]
+pub struct Environment {
+    pub os: String,
+    /// The user's login shell; commands themselves run with `sh -c`.
+    pub shell: Option<String>,
+    pub cwd: Option<PathBuf>,
+    /// Stack name and the manifest that revealed it.
+    pub stacks: Vec<(&'static str, String)>,
+    pub git: Option<GitState>,
+    /// Tool name and its version, `None` when it is not installed.
+    pub tools: Vec<(&'static str, Option<String>)>,
+}
+
+#[derive(Debug)]
+pub struct GitState {
+    pub branch: String,
+    /// Files with uncommitted changes, untracked ones included.
+    pub changes: usize,
+}
+
+impl Environment {
+    /// Looks at the OS, the current directory and the tools of its project.
+    /// Anything that cannot be determined is left out.
+    pub fn collect() -> Environment {
+        let cwd = std::env::current_dir().ok();
+        let stacks = cwd.as_deref().map(detect_stacks).unwrap_or_default();
+
+        let mut names = vec!["git"];
+        for (stack, _) in &stacks {
+            let tools = STACKS
+                .iter()
+                .filter(|(name, _, _)| name == stack)
+                .flat_map(|(_, _, tools)| tools.iter());
+            for tool in tools {
+                if !names.contains(tool) {
+                    names.push(tool);
+                }
+            }
+        }
+        // Each probe starts a process; run them side by side.
+        let probes: Vec<_> = names
+            .into_iter()
+            .map(|name| (name, std::thread::spawn(move || tool_version(name))))
+            .collect();
+        let git = git_state();
+        let tools = probes
+            .into_iter()
+            .map(|(name, probe)| (name, probe.join().ok().flatten()))
+            .collect();
+
+        Environment {
+            os: os_name(),
+            shell: std::env::var("SHELL").ok().map(|shell| {
+                Path::new(&shell)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or(shell)
+            }),
+            cwd,
+            stacks,
+            git,
+            tools,
+        }
+    }
+
+    /// A few lines for the start of the prompt.
+    pub fn summary(&self) -> String {
+        let mut lines = vec![
+            "Environment (collected by aic):".to_string(),
+            format!("- OS: {}", self.os),
+        ];
+        if let Some(shell) = &self.shell {
+            lines.push(format!("- Shell: {shell} (commands run with sh -c)"));
+        }
+        if let Some(cwd) = &self.cwd {
+            lines.push(format!("- Working directory: {}", cwd.display()));
+        }
+        if !self.stacks.is_empty() {
+            let stacks: Vec<String> = self
+                .stacks
+                .iter()
+                .map(|(stack, manifest)| format!("{stack} ({manifest})"))
+                .collect();
+            lines.push(format!("- Project: {}", stacks.join(", ")));
+        }
+        match &self.git {
+            Some(git) if git.changes == 0 => {
+                lines.push(format!("- Git: branch {}, clean", git.branch))
+            }
+            Some(git) => lines.push(format!(
+                "- Git: branch {}, {} changed file{}",
+                git.branch,
+                git.changes,
+                if git.changes == 1 { "" } else { "s" }
+            )),
+            None => lines.push("- Git: not a repository".to_string()),
+        }
+        let tools: Vec<String> = self
+            .tools
+            .iter()
+            .map(|(name, version)| match version {
+                Some(version) => format!("{name} {version}"),
+                None => format!("{name} not installed"),
+            })
+            .collect();
+        lines.push(format!("- Tools: {}", tools.join(", ")));
+        lines.join("\n")
+    }
+}
+
+/// `Ubuntu 24.04 LTS (linux x86_64)`, or just the platform when the
+/// distribution is unknown.
+fn os_name() -> String {
+    let platform = format!("{} {}", std::env::consts::OS, std::env::consts::ARCH);
+    let release = std::fs::read_to_string("/etc/os-release")
+        .ok()
+        .and_then(|text| {
+            text.lines().find_map(|line| {
+                let value = line.strip_prefix("PRETTY_NAME=")?;
+                Some(value.trim_matches('"').to_string())
+            })
+        })
+        .or_else(|| {
+            let version = command_output("sw_vers", &["-productVersion"])?;
+            Some(format!("macOS {}", version.trim()))
+        });
+    match release {
+        Some(release) => format!("{release} ({platform})"),
+        None => platform,
+    }
+}
+
+/// Stacks whose manifest is in `cwd` or, inside a git repository, a parent
+/// directory up to its root; nearest first.
+fn detect_stacks(cwd: &Path) -> Vec<(&'static str, String)> {
+    let repo = cwd.ancestors().find(|dir| dir.join(".git").exists());
+    let dirs = cwd
+        .ancestors()
+        .take_while(|dir| *dir == cwd || repo.is_some_and(|repo| dir.starts_with(repo)));
+    let mut stacks = Vec::new();
+    for dir in dirs {
+        for (stack, manifests, _) in STACKS {
+            if stacks.iter().any(|(name, _)| name == stack) {
+                continue;
+            }
+            if let Some(manifest) = manifests.iter().find(|m| dir.join(m).is_file()) {
+                let shown = match dir.strip_prefix(cwd) {
+                    Ok(rel) if rel.as_os_str().is_empty() => manifest.to_string(),
+                    _ => dir.join(manifest).display().to_string(),
+                };
+                stacks.push((*stack, shown));
+            }
+        }
+    }
+    stacks
+}
+
+/// Branch and changes of the repository around the current directory, also
+/// before the first commit.
+fn git_state() -> Option<GitState> {
+    let status = command_output("git", &["status", "--porcelain", "--branch"])?;
+    let mut lines = status.lines();
+    // `## main...origin/main [ahead 1]`, `## No commits yet on main` or `## HEAD (no branch)`
+    let head = lines.next()?.strip_prefix("## ")?;
+    let head = head.strip_prefix("No commits yet on ").unwrap_or(head);
+    let branch = match head.split_once("...") {
+        Some((branch, _)) => branch,
+        None => head.split(' ').next().unwrap_or(head),
+    };
+    Some(GitState {
+        branch: if branch == "HEAD" {
+            "(detached HEAD)".to_string()
+        } else {
+            branch.to_string()
+        },
+        changes: lines.count(),
+    })
+}
+
+/// The first version number `name --version` prints.
+fn tool_version(name: &str) -> Option<String> {
+    let output = probe(Command::new(name).arg("--version"))?;
+    // Some tools, e.g. older Java and Python, print their version on stderr.
+    let text = format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let version = Regex::new(r"\d+(\.\d+)+").ok()?;
+    version
+        .find(&text)
+        .map(|version| version.as_str().to_string())
+}
+
+/// Stdout of a command that succeeded.
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = probe(Command::new(program).args(args))?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Runs `command` to completion, or `None` if it cannot start or outlives
+/// [`PROBE_TIMEOUT`]. Probes print a line or two, which fits in the pipes
+/// while the child is polled.
+fn probe(command: &mut Command) -> Option<Output> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    let deadline = Instant::now() + PROBE_TIMEOUT;
+    while child.try_wait().ok()?.is_none() {
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    child.wait_with_output().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probes_report_what_the_tool_prints() {
+        let output =
+            probe(Command::new("sh").args(["-c", "echo tool 1.2.3; echo err >&2"])).unwrap();
+        assert_eq!(output.stdout, b"tool 1.2.3\n");
+        assert_eq!(output.stderr, b"err\n");
+        assert!(probe(&mut Command::new("aic-no-such-tool")).is_none());
+    }
+
+    #[test]
+    fn hung_probes_are_abandoned() {
+        let start = Instant::now();
+        assert!(probe(Command::new("sleep").arg("30")).is_none());
+        assert!(start.elapsed() < PROBE_TIMEOUT + Duration::from_secs(1));
+    }
+}
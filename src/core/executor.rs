@@ -1,10 +1,28 @@
-use std::process::{Command, Stdio};
+use crate::utils::shell;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Default number of bytes kept per stream by `execute_captured`.
+pub const DEFAULT_CAPTURE_LIMIT: usize = 64 * 1024;
+
+/// Time a timed-out command gets to exit after SIGTERM before it is killed.
+const KILL_GRACE: Duration = Duration::from_secs(3);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 macro_rules! wrap_error {
     ($msg:expr, $err:expr) => {
         CommandOutput {
             status_code: -1,
             output_str: Some(format!("{}: {}", $msg, $err)),
+            ..Default::default()
         }
     };
 }
@@ -12,46 +30,917 @@ macro_rules! wrap_error {
 #[derive(Debug)]
 pub struct CommandExecutor;
 
-#[derive(Debug)]
+/// How a command ended.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CommandStatus {
+    /// The command ran to completion (successfully or not).
+    #[default]
+    Exited,
+    /// The command exceeded its timeout and was terminated.
+    TimedOut,
+    /// The user cancelled the command with Ctrl-C.
+    Interrupted,
+}
+
+impl CommandStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommandStatus::Exited => "exited",
+            CommandStatus::TimedOut => "timed_out",
+            CommandStatus::Interrupted => "interrupted",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct CommandOutput {
+    pub status: CommandStatus,
     pub status_code: i32,
     pub output_str: Option<String>,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    /// Wall-clock time between spawning the shell and its exit.
+    pub duration: Duration,
+    /// Signal that terminated the process, if it did not exit normally.
+    pub signal: Option<i32>,
+}
+
+impl CommandOutput {
+    fn from_status(status: ExitStatus, kind: CommandStatus, duration: Duration) -> Self {
+        CommandOutput {
+            status: kind,
+            status_code: status.code().unwrap_or(-1),
+            duration,
+            signal: exit_signal(&status),
+            ..Default::default()
+        }
+    }
+
+    fn into_result(self) -> Result<CommandOutput, CommandOutput> {
+        if self.status == CommandStatus::Exited && self.status_code == 0 {
+            Ok(self)
+        } else {
+            Err(self)
+        }
+    }
+}
+
+/// How the commands of a session are run (`shell` setting).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ShellMode {
+    /// A fresh `sh -c` per command; `cd` and `export` are carried over.
+    #[default]
+    OneShot,
+    /// One long-lived `sh` per session, which also keeps functions, aliases,
+    /// unexported variables and sourced scripts such as a virtualenv.
+    Persistent,
 }
+
+impl ShellMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShellMode::OneShot => "one-shot",
+            ShellMode::Persistent => "persistent",
+        }
+    }
+}
+
+impl std::str::FromStr for ShellMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "one-shot" => Ok(ShellMode::OneShot),
+            "persistent" => Ok(ShellMode::Persistent),
+            _ => Err(format!("unknown shell mode {s:?}")),
+        }
+    }
+}
+
+/// Where the commands of one `ask` run or chat session execute: the working
+/// directory and exported variables the previous command left behind, so a
+/// `cd` or `export` in one step still holds in the next.
+#[derive(Debug)]
+pub struct ExecContext {
+    pub cwd: PathBuf,
+    env: BTreeMap<OsString, OsString>,
+    mode: ShellMode,
+    /// The session's shell in persistent mode, started by the first command
+    /// and again after it dies.
+    shell: Option<Shell>,
+    /// Aliases the shell had after the last command, by name.
+    aliases: BTreeMap<String, String>,
+}
+
+impl Default for ExecContext {
+    /// Starts from aic's own directory and environment.
+    fn default() -> Self {
+        ExecContext {
+            cwd: std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/")),
+            env: std::env::vars_os().collect(),
+            mode: ShellMode::OneShot,
+            shell: None,
+            aliases: BTreeMap::new(),
+        }
+    }
+}
+
+/// Env var naming the file the shell's exit trap writes its state to.
+const STATE_ENV: &str = "AIC_EXEC_STATE";
+
+/// Writes the last exit status, the directory and the exported variables to
+/// the state file, one per line with the variables separated by NULs.
+const SAVE_STATE: &str = r#"{ echo $?; pwd; env -0; } > "$AIC_EXEC_STATE" 2>/dev/null"#;
+
+/// Lists the persistent shell's aliases next to its state file.
+const SAVE_ALIASES: &str = r#"alias > "$AIC_EXEC_STATE-aliases" 2>/dev/null"#;
+
+/// Function the persistent shell runs each command in, so that an interrupt
+/// returns from it rather than ending the shell.
+const RUN_FUNCTION: &str = "__aic_run";
+
+/// Variables the shell sets for itself, not worth carrying over.
+const SHELL_VARS: &[&str] = &["_", "PWD", "SHLVL", STATE_ENV];
+
+impl ExecContext {
+    /// A context running its commands as `mode` says. The persistent shell
+    /// needs Unix job control; elsewhere commands run one-shot.
+    pub fn new(mode: ShellMode) -> Self {
+        ExecContext {
+            mode: if cfg!(target_family = "unix") {
+                mode
+            } else {
+                ShellMode::OneShot
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Aliases the next command may use, so they can be checked too. Only the
+    /// persistent shell keeps aliases from one command to the next.
+    pub fn aliases(&self) -> &BTreeMap<String, String> {
+        &self.aliases
+    }
+
+    /// A shell running `command_line` here that reports its final directory
+    /// and environment to a state file, read back by [`Self::update`].
+    fn command(&self, command_line: &str) -> (Command, PathBuf) {
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+        let state = std::env::temp_dir().join(format!(
+            "aic-state-{}-{}",
+            std::process::id(),
+            RUNS.fetch_add(1, Ordering::Relaxed)
+        ));
+        // The trap also runs after `exit`, and keeps the command's exit status.
+        #[cfg(target_family = "unix")]
+        let script = format!("trap '{SAVE_STATE}' EXIT\n{command_line}");
+        #[cfg(target_family = "windows")]
+        let script = command_line.to_string();
+        let mut cmd = get_shell_command(&script, false);
+        cmd.current_dir(&self.cwd)
+            .env_clear()
+            .envs(&self.env)
+            .env(STATE_ENV, &state);
+        (cmd, state)
+    }
+
+    /// Takes over the directory and environment the shell ended with and
+    /// returns the exit status it saved. A missing or partial state, e.g.
+    /// after a timeout, changes nothing.
+    #[cfg(target_family = "unix")]
+    fn update(&mut self, state: &std::path::Path) -> Option<i32> {
+        use std::os::unix::ffi::OsStringExt;
+        let bytes = std::fs::read(state).ok()?;
+        let _ = std::fs::remove_file(state);
+        let mut parts = bytes.splitn(3, |&b| b == b'\n');
+        let status = std::str::from_utf8(parts.next()?).ok()?.parse().ok();
+        let cwd = PathBuf::from(OsString::from_vec(parts.next()?.to_vec()));
+        if cwd.is_dir() {
+            self.cwd = cwd;
+        }
+        let vars = parts.next().unwrap_or_default();
+        // Nothing here means `env -0` is not supported, not an empty environment.
+        if vars.is_empty() {
+            return status;
+        }
+        self.env = vars
+            .split(|&b| b == 0)
+            .filter_map(|entry| {
+                let eq = entry.iter().position(|&b| b == b'=')?;
+                let name = OsString::from_vec(entry[..eq].to_vec());
+                if SHELL_VARS.iter().any(|var| name == *var) {
+                    return None;
+                }
+                Some((name, OsString::from_vec(entry[eq + 1..].to_vec())))
+            })
+            .collect();
+        status
+    }
+
+    #[cfg(target_family = "windows")]
+    fn update(&mut self, state: &std::path::Path) -> Option<i32> {
+        let _ = std::fs::remove_file(state);
+        None
+    }
+
+    /// Runs `command_line` in the session's shell, starting one in the last
+    /// known directory and environment if there is none or it has died.
+    fn run_persistent(
+        &mut self,
+        command_line: &str,
+        max_bytes: usize,
+        timeout: Option<Duration>,
+        echo: bool,
+    ) -> Result<CommandOutput, CommandOutput> {
+        let alive = match &mut self.shell {
+            Some(shell) => matches!(shell.child.try_wait(), Ok(None)),
+            None => false,
+        };
+        if !alive {
+            if self.shell.take().is_some() {
+                eprintln!(
+                    "\x1b[33m[shell] Started a new shell in {}\x1b[0m",
+                    self.cwd.display()
+                );
+            }
+            self.shell = Some(self.spawn_shell()?);
+        }
+        let shell = self.shell.as_mut().expect("the shell was just started");
+
+        let marker = shell.marker();
+        // `command eval` keeps a syntax error in the command from ending the shell.
+        // Ctrl-C and the timeout signal the shell's whole group: its commands
+        // die, and the traps make the shell return from the function instead.
+        let script = format!(
+            "{RUN_FUNCTION}() {{ trap 'return 130' INT; trap 'return 143' TERM; command eval '{}'; }}\n\
+             {RUN_FUNCTION} <{}\n{SAVE_STATE}\ntrap : INT; trap - TERM\n{SAVE_ALIASES}\n\
+             printf '%s\\n' {marker}; printf '%s\\n' {marker} >&2\n",
+            command_line.replace('\'', r"'\''"),
+            shell.input
+        );
+        let job = JobControl::new();
+        job.lend(&shell.child);
+        let start = Instant::now();
+        if let Some(stdin) = &mut shell.child.stdin {
+            // A failed write shows up below as the shell having exited.
+            let _ = stdin
+                .write_all(script.as_bytes())
+                .and_then(|_| stdin.flush());
+        }
+
+        let mut streams = [
+            Marked::new(&marker, echo.then_some(Stream::Stdout), max_bytes),
+            Marked::new(&marker, echo.then_some(Stream::Stderr), max_bytes),
+        ];
+        let mut deadline = timeout.map(|timeout| start + timeout);
+        let mut timed_out = false;
+        let ended = loop {
+            match shell.output.recv_timeout(POLL_INTERVAL) {
+                Ok((index, chunk)) => streams[index].push(&chunk),
+                Err(RecvTimeoutError::Disconnected) => thread::sleep(POLL_INTERVAL),
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+            if streams.iter().all(|stream| stream.done) {
+                break None;
+            }
+            match shell.child.try_wait() {
+                Ok(Some(status)) => break Some((status, status_kind(&status))),
+                Ok(None) => {}
+                Err(e) => return Err(wrap_error!("Failed to wait for shell", e)),
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                if timed_out {
+                    // The command outlived SIGTERM; only ending the shell stops it.
+                    terminate(&mut shell.child, true);
+                    let status = shell
+                        .child
+                        .wait()
+                        .map_err(|e| wrap_error!("Failed to wait for shell", e))?;
+                    break Some((status, CommandStatus::TimedOut));
+                }
+                terminate(&mut shell.child, false);
+                timed_out = true;
+                deadline = Some(Instant::now() + KILL_GRACE);
+            }
+        };
+        drop(job);
+
+        if ended.is_some() {
+            // Pick up what the shell wrote before it went away.
+            while let Ok((index, chunk)) = shell.output.recv_timeout(POLL_INTERVAL) {
+                streams[index].push(&chunk);
+            }
+        }
+        let [stdout, stderr] = streams.map(|stream| stream.finish());
+        let state = shell.state.clone();
+        let aliases = shell.aliases_file();
+        let saved = self.update(&state);
+        self.aliases = read_aliases(&aliases);
+        let duration = start.elapsed();
+
+        let output = match ended {
+            None => {
+                let code = saved.unwrap_or(-1);
+                CommandOutput {
+                    status: if timed_out {
+                        CommandStatus::TimedOut
+                    } else if code == 130 {
+                        CommandStatus::Interrupted
+                    } else {
+                        CommandStatus::Exited
+                    },
+                    status_code: code,
+                    duration,
+                    ..Default::default()
+                }
+            }
+            Some((status, kind)) => CommandOutput {
+                output_str: Some(format!(
+                    "{}; the next command starts a new one in the last directory and \
+                     environment, without earlier functions and aliases",
+                    match kind {
+                        CommandStatus::Interrupted => {
+                            "the command was interrupted, which ended the shell"
+                        }
+                        CommandStatus::TimedOut => {
+                            "the command ignored the timeout, so the shell was killed"
+                        }
+                        CommandStatus::Exited => "the shell exited",
+                    }
+                )),
+                ..CommandOutput::from_status(status, kind, duration)
+            },
+        };
+        CommandOutput {
+            stdout: Some(stdout),
+            stderr: Some(stderr),
+            ..output
+        }
+        .into_result()
+    }
+
+    fn spawn_shell(&self) -> Result<Shell, CommandOutput> {
+        static SHELLS: AtomicUsize = AtomicUsize::new(0);
+        let state = std::env::temp_dir().join(format!(
+            "aic-shell-{}-{}",
+            std::process::id(),
+            SHELLS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut cmd = Command::new("sh");
+        cmd.current_dir(&self.cwd)
+            .env_clear()
+            .envs(&self.env)
+            .env(STATE_ENV, &state)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        own_group(&mut cmd);
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| wrap_error!("Failed to start shell", e))?;
+
+        let (sender, output) = mpsc::channel();
+        for (index, pipe) in [
+            child
+                .stdout
+                .take()
+                .map(|p| Box::new(p) as Box<dyn Read + Send>),
+            child
+                .stderr
+                .take()
+                .map(|p| Box::new(p) as Box<dyn Read + Send>),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let (Some(mut pipe), sender) = (pipe, sender.clone()) else {
+                continue;
+            };
+            thread::spawn(move || {
+                let mut buf = [0u8; 8192];
+                loop {
+                    match pipe.read(&mut buf) {
+                        Ok(0) => break,
+                        Ok(n) => {
+                            if sender.send((index, buf[..n].to_vec())).is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                        Err(_) => break,
+                    }
+                }
+            });
+        }
+        if let Some(stdin) = &mut child.stdin {
+            // Also save the state when a command ends the shell with `exit`.
+            // A Ctrl-C that comes between commands is ignored.
+            let _ = writeln!(stdin, "trap '{SAVE_STATE}' EXIT\ntrap : INT");
+        }
+
+        Ok(Shell {
+            child,
+            output,
+            state,
+            // Commands must not read the script aic feeds the shell.
+            input: if JobControl::new().owns_terminal() {
+                "/dev/tty"
+            } else {
+                "/dev/null"
+            },
+            commands: 0,
+        })
+    }
+}
+
+/// A long-lived `sh` reading commands from aic, in its own process group.
+#[derive(Debug)]
+struct Shell {
+    child: Child,
+    /// Chunks of stdout (0) and stderr (1), in the order they were read.
+    output: Receiver<(usize, Vec<u8>)>,
+    /// File the shell saves its state to after every command.
+    state: PathBuf,
+    /// Where commands read their stdin from.
+    input: &'static str,
+    commands: usize,
+}
+
+impl Shell {
+    /// File the shell lists its aliases in after every command.
+    fn aliases_file(&self) -> PathBuf {
+        let mut path = self.state.clone().into_os_string();
+        path.push("-aliases");
+        PathBuf::from(path)
+    }
+
+    /// A line no command prints by accident, marking where its output ends.
+    fn marker(&mut self) -> String {
+        self.commands += 1;
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or_default();
+        format!("__aic_done_{}_{}_{nanos}", self.child.id(), self.commands)
+    }
+}
+
+impl Drop for Shell {
+    /// Closing its stdin ends the shell; it is killed if that does not.
+    fn drop(&mut self) {
+        drop(self.child.stdin.take());
+        let deadline = Instant::now() + KILL_GRACE;
+        while Instant::now() < deadline {
+            if !matches!(self.child.try_wait(), Ok(None)) {
+                break;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        if matches!(self.child.try_wait(), Ok(None)) {
+            terminate(&mut self.child, true);
+            let _ = self.child.wait();
+        }
+        let _ = std::fs::remove_file(&self.state);
+        let _ = std::fs::remove_file(self.aliases_file());
+    }
+}
+
+/// Reads the output of `alias`, which is `name='value'` in dash and
+/// `alias name='value'` in bash, one per line.
+fn read_aliases(path: &std::path::Path) -> BTreeMap<String, String> {
+    let text = std::fs::read_to_string(path).unwrap_or_default();
+    shell::parse(&text)
+        .into_iter()
+        .flat_map(|cmd| cmd.assignments.into_iter().chain(cmd.words))
+        .filter_map(|word| {
+            let (name, value) = word.split_once('=')?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// The output of one command in the persistent shell: everything up to its
+/// marker, printed as it arrives when `echo` names where.
+struct Marked {
+    marker: Vec<u8>,
+    echo: Option<Stream>,
+    /// Bytes that may be the start of the marker.
+    pending: Vec<u8>,
+    capture: Capture,
+    done: bool,
+}
+
+impl Marked {
+    fn new(marker: &str, echo: Option<Stream>, max_bytes: usize) -> Self {
+        Marked {
+            marker: format!("{marker}\n").into_bytes(),
+            echo,
+            pending: Vec::new(),
+            capture: Capture::new(max_bytes),
+            done: false,
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8]) {
+        if self.done {
+            // Output of background jobs after the command finished.
+            return;
+        }
+        self.pending.extend_from_slice(chunk);
+        let found = self
+            .pending
+            .windows(self.marker.len())
+            .position(|window| window == self.marker.as_slice());
+        let upto = match found {
+            Some(at) => at,
+            None => self.pending.len().saturating_sub(self.marker.len() - 1),
+        };
+        let ready: Vec<u8> = self.pending.drain(..upto).collect();
+        self.emit(&ready);
+        if found.is_some() {
+            self.pending.clear();
+            self.done = true;
+        }
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        let _ = match self.echo {
+            Some(Stream::Stdout) => std::io::stdout()
+                .write_all(bytes)
+                .and_then(|_| std::io::stdout().flush()),
+            Some(Stream::Stderr) => std::io::stderr().write_all(bytes),
+            None => Ok(()),
+        };
+        self.capture.push(bytes);
+    }
+
+    fn finish(mut self) -> String {
+        // Without a marker the shell died; what is pending is real output.
+        let pending = std::mem::take(&mut self.pending);
+        self.emit(&pending);
+        self.capture.text()
+    }
+}
+
 impl CommandExecutor {
-    pub fn execute_at_once(args: &str) -> Result<CommandOutput, CommandOutput> {
+    /// Runs the command with inherited stdio. When `timeout` elapses the command's
+    /// process group gets SIGTERM, then SIGKILL after a short grace period.
+    pub fn execute_at_once(
+        args: &str,
+        timeout: Option<Duration>,
+    ) -> Result<CommandOutput, CommandOutput> {
         //build the command
         let mut cmd = get_shell_command(args, false);
         cmd.stdin(Stdio::inherit());
         cmd.stdout(Stdio::inherit());
         cmd.stderr(Stdio::inherit());
 
+        let job = JobControl::new();
+        job.configure(&mut cmd);
+
         //run command async
+        let start = Instant::now();
         let mut child = cmd
             .spawn()
             .map_err(|e| wrap_error!("Failed to start shell", e))?;
+        job.attach(&child);
 
-        let status = match child.wait() {
+        let (status, kind) = match wait_child(&mut child, timeout) {
             Ok(s) => s,
             Err(e) => {
-                return Err(CommandOutput {
-                    status_code: -1,
-                    output_str: Some(format!("Failed to wait for child: {e}")),
-                });
+                return Err(wrap_error!("Failed to wait for child", e));
             }
         };
-        let status_code = status.code().unwrap_or(-1);
-        if status.success() {
-            Ok(CommandOutput {
-                status_code,
-                output_str: None,
-            })
-        } else {
-            Err(CommandOutput {
-                status_code,
-                output_str: None,
-            })
+        CommandOutput::from_status(status, kind, start.elapsed()).into_result()
+    }
+
+    /// Runs the command in `context` while teeing stdout and stderr live to the
+    /// terminal and collecting each stream separately. At most `max_bytes` are
+    /// kept per stream; anything beyond that is still printed but replaced by a
+    /// truncation marker. The directory and environment the command leaves
+    /// behind are kept in `context` for the next one.
+    pub fn execute_captured(
+        args: &str,
+        max_bytes: usize,
+        timeout: Option<Duration>,
+        context: &mut ExecContext,
+    ) -> Result<CommandOutput, CommandOutput> {
+        Self::capture(args, max_bytes, timeout, context, true)
+    }
+
+    /// Like `execute_captured`, but nothing is printed; for `--output json`.
+    pub fn execute_quiet(
+        args: &str,
+        max_bytes: usize,
+        timeout: Option<Duration>,
+        context: &mut ExecContext,
+    ) -> Result<CommandOutput, CommandOutput> {
+        Self::capture(args, max_bytes, timeout, context, false)
+    }
+
+    fn capture(
+        args: &str,
+        max_bytes: usize,
+        timeout: Option<Duration>,
+        context: &mut ExecContext,
+        echo: bool,
+    ) -> Result<CommandOutput, CommandOutput> {
+        if context.mode == ShellMode::Persistent {
+            return context.run_persistent(args, max_bytes, timeout, echo);
+        }
+        let (mut cmd, state) = context.command(args);
+        cmd.stdin(Stdio::inherit());
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+
+        let job = JobControl::new();
+        job.configure(&mut cmd);
+
+        let start = Instant::now();
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| wrap_error!("Failed to start shell", e))?;
+        job.attach(&child);
+
+        let stdout_pipe = child.stdout.take();
+        let stderr_pipe = child.stderr.take();
+        let stdout_reader = thread::spawn(move || match stdout_pipe {
+            Some(pipe) => tee(pipe, echo.then(std::io::stdout), max_bytes),
+            None => String::new(),
+        });
+        let stderr_reader = thread::spawn(move || match stderr_pipe {
+            Some(pipe) => tee(pipe, echo.then(std::io::stderr), max_bytes),
+            None => String::new(),
+        });
+
+        let status = wait_child(&mut child, timeout);
+        let stdout = stdout_reader.join().unwrap_or_default();
+        let stderr = stderr_reader.join().unwrap_or_default();
+        context.update(&state);
+        let (status, kind) = status.map_err(|e| wrap_error!("Failed to wait for child", e))?;
+
+        CommandOutput {
+            stdout: Some(stdout),
+            stderr: Some(stderr),
+            ..CommandOutput::from_status(status, kind, start.elapsed())
+        }
+        .into_result()
+    }
+}
+
+/// Copies `source` into `sink` (if any) until EOF, returning the first
+/// `max_bytes` read followed by a marker with the number of bytes that were dropped.
+fn tee(mut source: impl Read, mut sink: Option<impl Write>, max_bytes: usize) -> String {
+    let mut capture = Capture::new(max_bytes);
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = match source.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+        if let Some(sink) = &mut sink {
+            let _ = sink.write_all(&buf[..n]);
+            let _ = sink.flush();
+        }
+
+        capture.push(&buf[..n]);
+    }
+    capture.text()
+}
+
+/// The first `max_bytes` of a stream and a count of the rest.
+#[derive(Debug)]
+struct Capture {
+    bytes: Vec<u8>,
+    dropped: usize,
+    max_bytes: usize,
+}
+
+impl Capture {
+    fn new(max_bytes: usize) -> Self {
+        Capture {
+            bytes: Vec::new(),
+            dropped: 0,
+            max_bytes,
         }
     }
+
+    fn push(&mut self, data: &[u8]) {
+        let room = self.max_bytes.saturating_sub(self.bytes.len());
+        self.bytes.extend_from_slice(&data[..data.len().min(room)]);
+        self.dropped += data.len().saturating_sub(room);
+    }
+
+    /// The kept bytes followed by a marker with the number dropped.
+    fn text(self) -> String {
+        let mut text = String::from_utf8_lossy(&self.bytes).into_owned();
+        if self.dropped > 0 {
+            text.push_str(&format!("\n[... {} bytes truncated]", self.dropped));
+        }
+        text
+    }
+}
+
+/// Waits for the child, terminating its process group once `timeout` elapses.
+fn wait_child(
+    child: &mut Child,
+    timeout: Option<Duration>,
+) -> std::io::Result<(ExitStatus, CommandStatus)> {
+    let Some(timeout) = timeout else {
+        let status = child.wait()?;
+        return Ok((status, status_kind(&status)));
+    };
+
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status, status_kind(&status)));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    Ok((stop(child)?, CommandStatus::TimedOut))
+}
+
+/// Sends SIGTERM to the child's process group, then SIGKILL after a grace period.
+fn stop(child: &mut Child) -> std::io::Result<ExitStatus> {
+    terminate(child, false);
+    let grace_deadline = Instant::now() + KILL_GRACE;
+    while Instant::now() < grace_deadline {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    terminate(child, true);
+    child.wait()
+}
+
+/// A shell reports a child killed by Ctrl-C either as SIGINT or as exit code 130.
+fn status_kind(status: &ExitStatus) -> CommandStatus {
+    if exit_signal(status) == Some(SIGINT) || status.code() == Some(130) {
+        CommandStatus::Interrupted
+    } else {
+        CommandStatus::Exited
+    }
+}
+
+#[cfg(target_family = "unix")]
+const SIGINT: i32 = libc::SIGINT;
+
+#[cfg(target_family = "windows")]
+const SIGINT: i32 = 2;
+
+#[cfg(target_family = "unix")]
+fn exit_signal(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(target_family = "windows")]
+fn exit_signal(_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+/// Sends SIGTERM (or SIGKILL when `force` is set) to the child's whole process group.
+#[cfg(target_family = "unix")]
+fn terminate(child: &mut Child, force: bool) {
+    let signal = if force { libc::SIGKILL } else { libc::SIGTERM };
+    // SAFETY: the child leads its own process group (see `JobControl::configure`).
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), signal);
+    }
+}
+
+#[cfg(target_family = "windows")]
+fn terminate(child: &mut Child, _force: bool) {
+    let _ = child.kill();
+}
+
+/// Runs each command in its own process group so timeouts can kill everything it
+/// spawned. When aic owns the terminal, the group is made the foreground job so
+/// Ctrl-C reaches the command instead of aic; the terminal is handed back on drop.
+#[cfg(target_family = "unix")]
+struct JobControl {
+    tty: Option<std::fs::File>,
+}
+
+#[cfg(target_family = "unix")]
+impl JobControl {
+    fn new() -> Self {
+        use std::os::fd::AsRawFd;
+        let tty = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .ok()
+            // SAFETY: plain syscalls on a valid fd.
+            .filter(|tty| unsafe { libc::tcgetpgrp(tty.as_raw_fd()) == libc::getpgrp() });
+        JobControl { tty }
+    }
+
+    fn configure(&self, cmd: &mut Command) {
+        use std::os::fd::AsRawFd;
+        use std::os::unix::process::CommandExt;
+        let tty_fd = self.tty.as_ref().map(|tty| tty.as_raw_fd());
+        // SAFETY: only async-signal-safe libc calls run between fork and exec.
+        unsafe {
+            cmd.pre_exec(move || {
+                libc::setpgid(0, 0);
+                if let Some(fd) = tty_fd {
+                    give_terminal(fd, libc::getpid());
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// Whether aic runs in the foreground of a terminal.
+    fn owns_terminal(&self) -> bool {
+        self.tty.is_some()
+    }
+
+    /// Makes the group of `child`, started with [`own_group`], the foreground
+    /// job until this is dropped.
+    fn lend(&self, child: &Child) {
+        use std::os::fd::AsRawFd;
+        if let Some(tty) = &self.tty {
+            // SAFETY: plain syscalls on a valid fd.
+            unsafe { give_terminal(tty.as_raw_fd(), child.id() as libc::pid_t) };
+        }
+    }
+
+    /// Mirrors `configure` from the parent side so neither process races the other.
+    fn attach(&self, child: &Child) {
+        use std::os::fd::AsRawFd;
+        let pid = child.id() as libc::pid_t;
+        // SAFETY: plain syscalls; failures only mean the child already did the same.
+        unsafe {
+            libc::setpgid(pid, pid);
+            if let Some(tty) = &self.tty {
+                give_terminal(tty.as_raw_fd(), pid);
+            }
+        }
+    }
+}
+
+#[cfg(target_family = "unix")]
+impl Drop for JobControl {
+    fn drop(&mut self) {
+        use std::os::fd::AsRawFd;
+        if let Some(tty) = &self.tty {
+            // SAFETY: plain syscalls on a valid fd.
+            unsafe { give_terminal(tty.as_raw_fd(), libc::getpgrp()) };
+        }
+    }
+}
+
+/// Starts `cmd` in a process group of its own, leaving the terminal to aic.
+#[cfg(target_family = "unix")]
+fn own_group(cmd: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    cmd.process_group(0);
+}
+
+#[cfg(target_family = "windows")]
+fn own_group(_cmd: &mut Command) {}
+
+/// Makes `pgid` the foreground process group of `tty`. SIGTTOU is ignored during
+/// the call because background processes are otherwise stopped by it.
+#[cfg(target_family = "unix")]
+unsafe fn give_terminal(tty: libc::c_int, pgid: libc::pid_t) {
+    let previous = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+    libc::tcsetpgrp(tty, pgid);
+    libc::signal(libc::SIGTTOU, previous);
+}
+
+#[cfg(target_family = "windows")]
+struct JobControl;
+
+#[cfg(target_family = "windows")]
+impl JobControl {
+    fn new() -> Self {
+        JobControl
+    }
+
+    fn configure(&self, _cmd: &mut Command) {}
+
+    fn owns_terminal(&self) -> bool {
+        false
+    }
+
+    fn lend(&self, _child: &Child) {}
+
+    fn attach(&self, _child: &Child) {}
 }
 
 #[cfg(target_family = "unix")]
@@ -75,3 +964,172 @@ fn get_shell_command(command_line: &str, _use_sudo: bool) -> Command {
     c.arg("/C").arg(command_line);
     c
 }
+
+#[cfg(all(test, target_family = "unix"))]
+mod tests {
+    use super::*;
+
+    fn run(command: &str, context: &mut ExecContext) -> Result<CommandOutput, CommandOutput> {
+        CommandExecutor::execute_quiet(command, 4096, Some(Duration::from_secs(20)), context)
+    }
+
+    /// Presses Ctrl-C shortly, which signals the whole foreground group.
+    fn interrupt_soon(context: &ExecContext) -> thread::JoinHandle<()> {
+        let group = context.shell.as_ref().unwrap().child.id() as libc::pid_t;
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            // SAFETY: the shell leads its own process group.
+            unsafe { libc::kill(-group, libc::SIGINT) };
+        })
+    }
+
+    #[test]
+    fn persistent_state_survives_an_interrupted_command() {
+        let dir = std::env::temp_dir().join(format!("aic-exec-interrupt-{}", std::process::id()));
+        let sub = dir.join("sub");
+        std::fs::create_dir_all(&sub).unwrap();
+        let mut context = ExecContext::new(ShellMode::Persistent);
+        context.cwd = dir.clone();
+        run("greet() { echo hello; }; kept=local", &mut context).unwrap();
+        let shell = context.shell.as_ref().unwrap().child.id();
+
+        let interrupt = interrupt_soon(&context);
+        let interrupted = run(
+            "cd sub && export AIC_KEPT=yes && sleep 10 && echo finished",
+            &mut context,
+        )
+        .unwrap_err();
+        interrupt.join().unwrap();
+        assert_eq!(interrupted.status, CommandStatus::Interrupted);
+        assert_eq!(interrupted.stdout.as_deref(), Some(""));
+        assert_eq!(context.cwd, sub);
+
+        let next = run("pwd; echo \"$AIC_KEPT\"; greet; echo $kept", &mut context).unwrap();
+        assert_eq!(
+            next.stdout,
+            Some(format!("{}\nyes\nhello\nlocal\n", sub.display()))
+        );
+
+        // A loop stops too, instead of moving on to its next iteration.
+        let interrupt = interrupt_soon(&context);
+        let start = Instant::now();
+        let looped = run("while :; do sleep 1; done", &mut context).unwrap_err();
+        interrupt.join().unwrap();
+        assert_eq!(looped.status, CommandStatus::Interrupted);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(context.shell.as_ref().unwrap().child.id(), shell);
+
+        drop(context);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn persistent_state_survives_a_timeout() {
+        let dir = std::env::temp_dir().join(format!("aic-exec-timeout-{}", std::process::id()));
+        let sub = dir.join("sub");
+        std::fs::create_dir_all(&sub).unwrap();
+        let mut context = ExecContext::new(ShellMode::Persistent);
+        context.cwd = dir.clone();
+        run("greet() { echo hello; }", &mut context).unwrap();
+
+        let timed_out = CommandExecutor::execute_quiet(
+            "cd sub && sleep 10",
+            4096,
+            Some(Duration::from_millis(300)),
+            &mut context,
+        )
+        .unwrap_err();
+        assert_eq!(timed_out.status, CommandStatus::TimedOut);
+        assert_eq!(context.cwd, sub);
+        let next = run("greet", &mut context).unwrap();
+        assert_eq!(next.stdout.as_deref(), Some("hello\n"));
+
+        run("alias hi='echo \"it'\\''s me\"'", &mut context).unwrap();
+        assert_eq!(
+            context.aliases().get("hi").map(String::as_str),
+            Some("echo \"it's me\"")
+        );
+
+        drop(context);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// `sh -c script` in a process group of its own, as the executor starts it.
+    fn spawn(script: &str) -> Child {
+        let mut cmd = get_shell_command(script, false);
+        own_group(&mut cmd);
+        cmd.spawn().unwrap()
+    }
+
+    #[test]
+    fn capture_keeps_the_first_bytes_and_counts_the_rest() {
+        let mut capture = Capture::new(5);
+        capture.push(b"hel");
+        capture.push(b"lo world");
+        assert_eq!(capture.text(), "hello\n[... 6 bytes truncated]");
+
+        let mut within = Capture::new(5);
+        within.push(b"hello");
+        assert_eq!(within.text(), "hello");
+
+        // The sink still gets everything.
+        let mut sink = Vec::new();
+        assert_eq!(
+            tee(&b"abcdefgh"[..], Some(&mut sink), 3),
+            "abc\n[... 5 bytes truncated]"
+        );
+        assert_eq!(sink, b"abcdefgh");
+    }
+
+    #[test]
+    fn captured_output_is_capped_per_stream() {
+        let mut context = ExecContext::default();
+        let output = CommandExecutor::execute_quiet(
+            "printf '%0100d' 0; printf 'err'>&2",
+            10,
+            None,
+            &mut context,
+        )
+        .unwrap();
+        assert_eq!(
+            output.stdout.as_deref(),
+            Some("0000000000\n[... 90 bytes truncated]")
+        );
+        assert_eq!(output.stderr.as_deref(), Some("err"));
+    }
+
+    #[test]
+    fn a_command_past_its_timeout_is_timed_out() {
+        let mut child = spawn("sleep 30");
+        let start = Instant::now();
+        let (status, kind) = wait_child(&mut child, Some(Duration::from_millis(200))).unwrap();
+        assert_eq!(kind, CommandStatus::TimedOut);
+        assert_eq!(exit_signal(&status), Some(libc::SIGTERM));
+        assert!(start.elapsed() < KILL_GRACE);
+
+        let mut context = ExecContext::default();
+        let output = CommandExecutor::execute_quiet(
+            "echo started; sleep 30",
+            4096,
+            Some(Duration::from_millis(200)),
+            &mut context,
+        )
+        .unwrap_err();
+        assert_eq!(output.status, CommandStatus::TimedOut);
+        assert_eq!(output.stdout.as_deref(), Some("started\n"));
+
+        let (_, kind) = wait_child(&mut spawn("exit 3"), Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(kind, CommandStatus::Exited);
+    }
+
+    #[test]
+    fn stop_kills_what_ignores_sigterm() {
+        let mut child = spawn("trap '' TERM; sleep 30");
+        // Let the shell install the trap first.
+        thread::sleep(Duration::from_millis(200));
+        let start = Instant::now();
+        let status = stop(&mut child).unwrap();
+        assert_eq!(exit_signal(&status), Some(libc::SIGKILL));
+        assert!(start.elapsed() >= KILL_GRACE);
+    }
+}
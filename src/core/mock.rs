@@ -0,0 +1,222 @@
+use crate::core::ai_client::{ChatEvents, ChatReply, LlmBackend};
+use crate::core::context;
+use futures::future::LocalBoxFuture;
+use futures::StreamExt;
+use genai::chat::{
+    ChatMessage, ChatOptions, ChatRequest, ChatStreamEvent, ContentPart, MessageContent,
+    StreamChunk, StreamEnd, ToolCall, Usage,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+
+/// Shape of a fixture file. Each request takes the next response in order:
+///
+/// ```toml
+/// [[responses]]
+/// tool_calls = [{ arguments = { command = "ls", info = "list files" } }]
+///
+/// [[responses]]
+/// expect = "Cargo.toml"
+/// text = "The project has a Cargo.toml."
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Fixture {
+    #[serde(default)]
+    responses: Vec<MockResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MockResponse {
+    /// Text the last message of the request must contain, e.g. a tool result.
+    expect: Option<String>,
+    text: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<MockToolCall>,
+    /// Fail the request with this message instead of answering.
+    error: Option<String>,
+    /// Reported usage; estimated from the request and answer when unset.
+    prompt_tokens: Option<i32>,
+    completion_tokens: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MockToolCall {
+    #[serde(default = "default_tool")]
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+fn default_tool() -> String {
+    "run_commandes".to_string()
+}
+
+/// Answers requests with the scripted responses of a fixture file, without
+/// network or API key. Selected with `--model mock:<fixture>`.
+pub struct MockBackend {
+    path: PathBuf,
+    responses: Vec<MockResponse>,
+    /// Index of the next response.
+    next: Cell<usize>,
+}
+
+impl MockBackend {
+    pub fn load(path: &Path) -> Result<MockBackend, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        let fixture: Fixture = toml::from_str(&text)
+            .map_err(|e| format!("Invalid mock fixture {}: {e}", path.display()))?;
+        Ok(MockBackend {
+            path: path.to_path_buf(),
+            responses: fixture.responses,
+            next: Cell::new(0),
+        })
+    }
+
+    /// Takes the next response and checks it against `chat_req`.
+    fn respond(&self, chat_req: &ChatRequest) -> Result<(MessageContent, Usage), String> {
+        let index = self.next.get();
+        let response = self.responses.get(index).ok_or_else(|| {
+            format!(
+                "{}: no scripted response left for request {}",
+                self.path.display(),
+                index + 1
+            )
+        })?;
+        self.next.set(index + 1);
+
+        let last = chat_req
+            .messages
+            .last()
+            .map(message_text)
+            .unwrap_or_default();
+        if let Some(expect) = &response.expect {
+            if !last.contains(expect.as_str()) {
+                return Err(format!(
+                    "{}: response {} expects the last message to contain {expect:?}, got {last:?}",
+                    self.path.display(),
+                    index + 1
+                ));
+            }
+        }
+        if let Some(error) = &response.error {
+            return Err(error.clone());
+        }
+
+        let content = if response.tool_calls.is_empty() {
+            MessageContent::Text(response.text.clone().unwrap_or_default())
+        } else {
+            let calls = response.tool_calls.iter().enumerate();
+            MessageContent::ToolCalls(
+                calls
+                    .map(|(call, tool_call)| ToolCall {
+                        call_id: format!("call_{}_{call}", index + 1),
+                        fn_name: tool_call.name.clone(),
+                        fn_arguments: tool_call.arguments.clone(),
+                    })
+                    .collect(),
+            )
+        };
+
+        let prompt_tokens = response.prompt_tokens.unwrap_or_else(|| {
+            let system = chat_req.system.as_deref().unwrap_or_default();
+            let messages = chat_req.messages.iter().map(context::estimate_message);
+            (context::estimate_tokens(system) + messages.sum::<usize>()) as i32
+        });
+        let completion_tokens = response.completion_tokens.unwrap_or_else(|| {
+            let answer = ChatMessage::assistant(content.clone());
+            context::estimate_message(&answer) as i32
+        });
+        let usage = Usage {
+            prompt_tokens: Some(prompt_tokens),
+            completion_tokens: Some(completion_tokens),
+            total_tokens: Some(prompt_tokens + completion_tokens),
+            ..Usage::default()
+        };
+        Ok((content, usage))
+    }
+}
+
+impl LlmBackend for MockBackend {
+    fn exec_chat<'a>(
+        &'a self,
+        _model: &'a str,
+        chat_req: ChatRequest,
+        _options: Option<&'a ChatOptions>,
+    ) -> LocalBoxFuture<'a, Result<ChatReply, Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            let (content, usage) = self.respond(&chat_req)?;
+            Ok(ChatReply {
+                content: Some(content),
+                usage,
+            })
+        })
+    }
+
+    fn exec_chat_stream<'a>(
+        &'a self,
+        _model: &'a str,
+        chat_req: ChatRequest,
+        options: Option<&'a ChatOptions>,
+    ) -> LocalBoxFuture<'a, Result<ChatEvents, Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            let (content, usage) = self.respond(&chat_req)?;
+            let MessageContent::Text(text) = content else {
+                return Err(format!(
+                    "{}: streamed responses cannot carry tool calls",
+                    self.path.display()
+                )
+                .into());
+            };
+
+            Ok(text_stream(&text, usage, options))
+        })
+    }
+}
+
+/// Streams a complete answer word by word, so consumers see it arrive in
+/// pieces as from a provider.
+pub fn text_stream(text: &str, usage: Usage, options: Option<&ChatOptions>) -> ChatEvents {
+    let mut events = vec![ChatStreamEvent::Start];
+    events.extend(text.split_inclusive(char::is_whitespace).map(|word| {
+        ChatStreamEvent::Chunk(StreamChunk {
+            content: word.to_string(),
+        })
+    }));
+    let capture_usage = options.and_then(|o| o.capture_usage).unwrap_or(false);
+    events.push(ChatStreamEvent::End(StreamEnd {
+        captured_usage: capture_usage.then_some(usage),
+        ..StreamEnd::default()
+    }));
+    futures::stream::iter(events.into_iter().map(Ok)).boxed_local()
+}
+
+/// Text of a message as the fixture's `expect` sees it.
+fn message_text(message: &ChatMessage) -> String {
+    match &message.content {
+        MessageContent::Text(text) => text.clone(),
+        MessageContent::Parts(parts) => parts
+            .iter()
+            .filter_map(|part| match part {
+                ContentPart::Text(text) => Some(text.as_str()),
+                ContentPart::Image { .. } => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        MessageContent::ToolCalls(calls) => calls
+            .iter()
+            .map(|call| call.fn_arguments.to_string())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        MessageContent::ToolResponses(responses) => responses
+            .iter()
+            .map(|response| response.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
@@ -1,2 +1,9 @@
 pub mod ai_client;
+pub mod config;
+pub mod context;
+pub mod environment;
 pub mod executor;
+pub mod mock;
+pub mod record;
+pub mod session;
+pub mod usage;
@@ -0,0 +1,204 @@
+use crate::core::ai_client::{ChatEvents, ChatReply, LlmBackend};
+use crate::core::mock;
+use futures::future::LocalBoxFuture;
+use futures::StreamExt;
+use genai::chat::{ChatOptions, ChatRequest, ChatStreamEvent, MessageContent};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+
+/// One request and what came back, stored as `NNNN.json`. Tool calls are part
+/// of the reply and tool results part of the next request.
+#[derive(Debug, Serialize, Deserialize)]
+struct Exchange {
+    model: String,
+    /// Whether the answer was streamed.
+    stream: bool,
+    request: ChatRequest,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reply: Option<ChatReply>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+fn exchange_path(dir: &Path, number: usize) -> PathBuf {
+    dir.join(format!("{number:04}.json"))
+}
+
+/// Passes requests to another backend and writes every exchange to a directory
+/// (`AIC_RECORD`).
+pub struct Recorder {
+    dir: PathBuf,
+    inner: Box<dyn LlmBackend>,
+    /// Number of the next exchange file.
+    next: Cell<usize>,
+}
+
+impl Recorder {
+    /// Records into `dir`, after the exchanges already there.
+    pub fn create(dir: &Path, inner: Box<dyn LlmBackend>) -> Result<Recorder, String> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+        let mut next = 1;
+        while exchange_path(dir, next).exists() {
+            next += 1;
+        }
+        Ok(Recorder {
+            dir: dir.to_path_buf(),
+            inner,
+            next: Cell::new(next),
+        })
+    }
+
+    fn take_path(&self) -> PathBuf {
+        let number = self.next.get();
+        self.next.set(number + 1);
+        exchange_path(&self.dir, number)
+    }
+}
+
+/// Writes an exchange; a recording that cannot be written does not fail the request.
+fn write_exchange(path: &Path, exchange: &Exchange) {
+    let result = serde_json::to_string_pretty(exchange)
+        .map_err(|e| e.to_string())
+        .and_then(|json| std::fs::write(path, json).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        eprintln!("\x1b[33mFailed to record {}\x1b[0m: {e}", path.display());
+    }
+}
+
+impl LlmBackend for Recorder {
+    fn exec_chat<'a>(
+        &'a self,
+        model: &'a str,
+        chat_req: ChatRequest,
+        options: Option<&'a ChatOptions>,
+    ) -> LocalBoxFuture<'a, Result<ChatReply, Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            let path = self.take_path();
+            let result = self.inner.exec_chat(model, chat_req.clone(), options).await;
+            let exchange = Exchange {
+                model: model.to_string(),
+                stream: false,
+                request: chat_req,
+                reply: result.as_ref().ok().cloned(),
+                error: result.as_ref().err().map(|e| e.to_string()),
+            };
+            write_exchange(&path, &exchange);
+            result
+        })
+    }
+
+    fn exec_chat_stream<'a>(
+        &'a self,
+        model: &'a str,
+        chat_req: ChatRequest,
+        options: Option<&'a ChatOptions>,
+    ) -> LocalBoxFuture<'a, Result<ChatEvents, Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            let path = self.take_path();
+            let mut exchange = Exchange {
+                model: model.to_string(),
+                stream: true,
+                request: chat_req.clone(),
+                reply: None,
+                error: None,
+            };
+            let stream = match self.inner.exec_chat_stream(model, chat_req, options).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    exchange.error = Some(e.to_string());
+                    write_exchange(&path, &exchange);
+                    return Err(e);
+                }
+            };
+
+            // The exchange is written once the stream ends or fails.
+            let mut text = String::new();
+            let stream = stream.map(move |event| {
+                match &event {
+                    Ok(ChatStreamEvent::Chunk(chunk)) => text.push_str(&chunk.content),
+                    Ok(ChatStreamEvent::End(end)) => {
+                        exchange.reply = Some(ChatReply {
+                            content: Some(MessageContent::Text(std::mem::take(&mut text))),
+                            usage: end.captured_usage.clone().unwrap_or_default(),
+                        });
+                        write_exchange(&path, &exchange);
+                    }
+                    Err(e) => {
+                        exchange.error = Some(e.to_string());
+                        write_exchange(&path, &exchange);
+                    }
+                    _ => {}
+                }
+                event
+            });
+            Ok(stream.boxed_local())
+        })
+    }
+}
+
+/// Serves the replies of a recorded directory in order instead of asking a
+/// model (`AIC_REPLAY`).
+pub struct Replayer {
+    dir: PathBuf,
+    /// Number of the next exchange file.
+    next: Cell<usize>,
+}
+
+impl Replayer {
+    pub fn open(dir: &Path) -> Result<Replayer, String> {
+        if !exchange_path(dir, 1).is_file() {
+            return Err(format!("No recorded exchanges in {}", dir.display()));
+        }
+        Ok(Replayer {
+            dir: dir.to_path_buf(),
+            next: Cell::new(1),
+        })
+    }
+
+    /// Reads the next exchange, turning a recorded failure into an error.
+    fn replay(&self) -> Result<ChatReply, Box<dyn std::error::Error>> {
+        let number = self.next.get();
+        self.next.set(number + 1);
+        let path = exchange_path(&self.dir, number);
+        let text = std::fs::read_to_string(&path).map_err(|_| {
+            format!(
+                "No recorded exchange {number} in {} to replay",
+                self.dir.display()
+            )
+        })?;
+        let exchange: Exchange = serde_json::from_str(&text)
+            .map_err(|e| format!("Invalid recording {}: {e}", path.display()))?;
+        match (exchange.reply, exchange.error) {
+            (Some(reply), _) => Ok(reply),
+            (None, Some(error)) => Err(error.into()),
+            (None, None) => Err(format!("{} has no reply", path.display()).into()),
+        }
+    }
+}
+
+impl LlmBackend for Replayer {
+    fn exec_chat<'a>(
+        &'a self,
+        _model: &'a str,
+        _chat_req: ChatRequest,
+        _options: Option<&'a ChatOptions>,
+    ) -> LocalBoxFuture<'a, Result<ChatReply, Box<dyn std::error::Error>>> {
+        Box::pin(async move { self.replay() })
+    }
+
+    fn exec_chat_stream<'a>(
+        &'a self,
+        _model: &'a str,
+        _chat_req: ChatRequest,
+        options: Option<&'a ChatOptions>,
+    ) -> LocalBoxFuture<'a, Result<ChatEvents, Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            let reply = self.replay()?;
+            let usage = reply.usage.clone();
+            let text = reply.content_text_into_string().unwrap_or_default();
+            Ok(mock::text_stream(&text, usage, options))
+        })
+    }
+}
@@ -0,0 +1,194 @@
+use crate::core::config;
+use genai::chat::{ChatMessage, ChatRequest, MessageContent};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Longest session title, in characters.
+const TITLE_LEN: usize = 60;
+
+/// A chat conversation stored as `<id>.json` under the sessions directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub title: String,
+    pub model: String,
+    pub system: String,
+    /// Unix timestamps in seconds.
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub messages: Vec<ChatMessage>,
+}
+
+impl Session {
+    pub fn new(model: &str, system: &str) -> Session {
+        let now = now();
+        Session {
+            id: new_id(),
+            title: String::new(),
+            model: model.to_string(),
+            system: system.to_string(),
+            created_at: now,
+            updated_at: now,
+            messages: Vec::new(),
+        }
+    }
+
+    /// Appends a message, taking the title from the first user message.
+    pub fn push(&mut self, message: ChatMessage) {
+        if self.title.is_empty() {
+            if let Some(text) = message_text(&message) {
+                self.title = title_from(text);
+            }
+        }
+        self.messages.push(message);
+    }
+
+    pub fn chat_request(&self) -> ChatRequest {
+        ChatRequest::new(self.messages.clone()).with_system(self.system.as_str())
+    }
+
+    /// The file [`Session::save`] writes to.
+    pub fn path(&self) -> Result<PathBuf, String> {
+        sessions_dir().map(|dir| dir.join(format!("{}.json", self.id)))
+    }
+
+    /// Writes the session to the sessions directory, replacing the previous copy.
+    pub fn save(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.updated_at = now();
+        let dir = sessions_dir()?;
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+        self.write_to(&self.path()?)
+    }
+
+    /// Writes the session as JSON to `path`, atomically.
+    pub fn write_to(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .map_err(|e| format!("Failed to write {}: {e}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+        Ok(())
+    }
+
+    /// Reads a session written by [`Session::write_to`].
+    pub fn read_from(path: &Path) -> Result<Session, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        serde_json::from_str(&text)
+            .map_err(|e| format!("Invalid session {}: {e}", path.display()).into())
+    }
+}
+
+/// `$XDG_DATA_HOME/aic/sessions`, falling back to `~/.local/share/aic/sessions`.
+pub fn sessions_dir() -> Result<PathBuf, String> {
+    config::user_data_dir()
+        .map(|dir| dir.join("sessions"))
+        .ok_or_else(|| "Cannot locate the data directory: $HOME is not set".to_string())
+}
+
+/// Every stored session, most recently updated first. Unreadable files are skipped.
+pub fn list() -> Result<Vec<Session>, Box<dyn std::error::Error>> {
+    let dir = sessions_dir()?;
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read {}: {e}", dir.display()).into()),
+    };
+
+    let mut sessions: Vec<Session> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .filter_map(|text| serde_json::from_str(&text).ok())
+        .collect();
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.updated_at));
+    Ok(sessions)
+}
+
+/// Looks a session up by `last`, its full id or a unique id prefix.
+pub fn find(id: &str) -> Result<Session, Box<dyn std::error::Error>> {
+    let sessions = list()?;
+    if id == "last" {
+        return sessions
+            .into_iter()
+            .next()
+            .ok_or_else(|| "No saved chat sessions".into());
+    }
+    if let Some(session) = sessions.iter().find(|s| s.id == id) {
+        return Ok(session.clone());
+    }
+
+    let mut matches: Vec<Session> = sessions
+        .into_iter()
+        .filter(|s| s.id.starts_with(id))
+        .collect();
+    match matches.len() {
+        0 => Err(format!("No chat session {id:?} (see `aic chat --list`)").into()),
+        1 => Ok(matches.remove(0)),
+        n => Err(format!("{n} chat sessions start with {id:?}, use more characters").into()),
+    }
+}
+
+/// Removes a session and returns it.
+pub fn delete(id: &str) -> Result<Session, Box<dyn std::error::Error>> {
+    let session = find(id)?;
+    let path = session.path()?;
+    std::fs::remove_file(&path).map_err(|e| format!("Failed to delete {}: {e}", path.display()))?;
+    Ok(session)
+}
+
+/// Text of a plain text message.
+pub fn message_text(message: &ChatMessage) -> Option<&str> {
+    match &message.content {
+        MessageContent::Text(text) => Some(text.as_str()),
+        _ => None,
+    }
+}
+
+/// First line of `text`, shortened to [`TITLE_LEN`] characters.
+fn title_from(text: &str) -> String {
+    let line = text.trim().lines().next().unwrap_or_default().trim();
+    if line.chars().count() > TITLE_LEN {
+        let short: String = line.chars().take(TITLE_LEN - 1).collect();
+        format!("{}…", short.trim_end())
+    } else {
+        line.to_string()
+    }
+}
+
+/// Time since `timestamp`, e.g. `5m ago` or `3d ago`.
+pub fn age(timestamp: u64) -> String {
+    let secs = now().saturating_sub(timestamp);
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+/// Current Unix time in seconds.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Short, sortable id: the creation time in milliseconds, base 36.
+fn new_id() -> String {
+    let mut millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let mut id = Vec::new();
+    while millis > 0 {
+        id.push(b"0123456789abcdefghijklmnopqrstuvwxyz"[(millis % 36) as usize]);
+        millis /= 36;
+    }
+    id.reverse();
+    String::from_utf8(id).unwrap_or_default()
+}
@@ -0,0 +1,201 @@
+use crate::core::ai_client;
+use crate::core::config::{self, Config};
+use crate::core::session;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+
+/// Price of a model in USD per million tokens.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Price {
+    pub input: f64,
+    pub output: f64,
+}
+
+/// List prices by model name prefix, used when `[prices]` has no entry for a
+/// model. The longest matching prefix wins.
+const DEFAULT_PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4.1-nano", 0.10, 0.40),
+    ("gpt-4.1-mini", 0.40, 1.60),
+    ("gpt-4.1", 2.00, 8.00),
+    ("o3-mini", 1.10, 4.40),
+    ("o4-mini", 1.10, 4.40),
+    ("claude-3-haiku", 0.25, 1.25),
+    ("claude-3-5-haiku", 0.80, 4.00),
+    ("claude-3-5-sonnet", 3.00, 15.00),
+    ("claude-3-7-sonnet", 3.00, 15.00),
+    ("claude-sonnet-4", 3.00, 15.00),
+    ("claude-opus-4", 15.00, 75.00),
+    ("gemini-1.5-flash", 0.075, 0.30),
+    ("gemini-1.5-pro", 1.25, 5.00),
+    ("gemini-2.0-flash-lite", 0.075, 0.30),
+    ("gemini-2.0-flash", 0.10, 0.40),
+    ("gemini-2.5-flash", 0.30, 2.50),
+    ("gemini-2.5-pro", 1.25, 10.00),
+    ("deepseek-chat", 0.27, 1.10),
+];
+
+/// Price of `model`: the `[prices]` entry with the longest matching prefix,
+/// else the built-in list price. `None` for models with no known price.
+pub fn price(model: &str, config: &Config) -> Option<Price> {
+    let configured = config
+        .prices
+        .iter()
+        .filter(|(name, _)| model.starts_with(name.as_str()))
+        .max_by_key(|(name, _)| name.len())
+        .map(|(_, price)| price.value);
+    configured.or_else(|| {
+        DEFAULT_PRICES
+            .iter()
+            .filter(|(prefix, _, _)| model.starts_with(prefix))
+            .max_by_key(|(prefix, _, _)| prefix.len())
+            .map(|&(_, input, output)| Price { input, output })
+    })
+}
+
+/// One model call, as stored in the ledger.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Unix timestamp in seconds.
+    pub timestamp: u64,
+    /// The aic command that made the call: `ask` or `chat`.
+    pub command: String,
+    /// Chat session id.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    pub model: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// USD at the price known when the call was made.
+    pub cost: Option<f64>,
+}
+
+/// `usage.jsonl` under the data directory, one [`Entry`] per line.
+pub fn ledger_path() -> Result<PathBuf, String> {
+    config::user_data_dir()
+        .map(|dir| dir.join("usage.jsonl"))
+        .ok_or_else(|| "Cannot locate the data directory: $HOME is not set".to_string())
+}
+
+/// Prices the usage reported for one call and appends it to the ledger.
+/// A ledger that cannot be written is reported but does not fail the call.
+pub fn record(
+    command: &str,
+    session: Option<&str>,
+    model: &str,
+    usage: &genai::chat::Usage,
+    config: &Config,
+) -> Entry {
+    let tokens = |count: Option<i32>| count.unwrap_or_default().max(0) as u64;
+    let prompt_tokens = tokens(usage.prompt_tokens);
+    let completion_tokens = tokens(usage.completion_tokens);
+    let entry = Entry {
+        timestamp: session::now(),
+        command: command.to_string(),
+        session: session.map(str::to_string),
+        model: model.to_string(),
+        prompt_tokens,
+        completion_tokens,
+        cost: price(model, config).map(|price| {
+            (prompt_tokens as f64 * price.input + completion_tokens as f64 * price.output)
+                / 1_000_000.0
+        }),
+    };
+    // Replayed calls cost nothing and stay out of the ledger.
+    if std::env::var_os(ai_client::REPLAY_ENV).is_some_and(|dir| !dir.is_empty()) {
+        return entry;
+    }
+    if let Err(e) = append(&entry) {
+        eprintln!("\x1b[33mFailed to record usage\x1b[0m: {e}");
+    }
+    entry
+}
+
+fn append(entry: &Entry) -> Result<(), Box<dyn std::error::Error>> {
+    let path = ledger_path()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to open {}: {e}", path.display()))?;
+    // One write per line keeps concurrent appends from interleaving.
+    let line = format!("{}\n", serde_json::to_string(entry)?);
+    file.write_all(line.as_bytes())
+        .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+    Ok(())
+}
+
+/// Ledger entries made at or after `since`. Malformed lines are skipped.
+pub fn read_ledger(since: u64) -> Result<Vec<Entry>, Box<dyn std::error::Error>> {
+    let path = ledger_path()?;
+    let file = match std::fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read {}: {e}", path.display()).into()),
+    };
+    Ok(std::io::BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str::<Entry>(&line).ok())
+        .filter(|entry| entry.timestamp >= since)
+        .collect())
+}
+
+/// Running totals over several calls.
+#[derive(Debug, Clone, Default)]
+pub struct Tally {
+    pub requests: usize,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Sum of the priced calls.
+    pub cost: f64,
+    /// Calls whose model has no known price.
+    pub unpriced: usize,
+}
+
+impl Tally {
+    pub fn add(&mut self, entry: &Entry) {
+        self.requests += 1;
+        self.prompt_tokens += entry.prompt_tokens;
+        self.completion_tokens += entry.completion_tokens;
+        match entry.cost {
+            Some(cost) => self.cost += cost,
+            None => self.unpriced += 1,
+        }
+    }
+
+    /// `$0.0123`, `?` when no call was priced.
+    pub fn cost_text(&self) -> String {
+        if self.unpriced == self.requests && self.requests > 0 {
+            "?".to_string()
+        } else {
+            format!("${:.4}", self.cost)
+        }
+    }
+}
+
+impl fmt::Display for Tally {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} request{}, {} prompt + {} completion tokens, cost {}",
+            self.requests,
+            if self.requests == 1 { "" } else { "s" },
+            self.prompt_tokens,
+            self.completion_tokens,
+            self.cost_text()
+        )?;
+        if self.unpriced > 0 && self.unpriced < self.requests {
+            write!(f, " ({} without a known price)", self.unpriced)?;
+        }
+        Ok(())
+    }
+}
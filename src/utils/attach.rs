@@ -0,0 +1,295 @@
+use crate::utils::input;
+use crate::utils::paths;
+use glob::{MatchOptions, Pattern};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Default number of bytes of file content attached to one message.
+pub const DEFAULT_ATTACH_LIMIT: usize = 64 * 1024;
+
+/// Bytes searched for a NUL to tell binary files from text, as git does.
+const SNIFF_BYTES: usize = 8000;
+
+/// `*` stops at `/`, as in `.gitignore`.
+const IGNORE_MATCH: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Files attached to one message, within a byte budget shared by all of them.
+#[derive(Debug)]
+pub struct Attachments {
+    /// One fenced block per file, headed by its name.
+    pub text: String,
+    pub files: Vec<String>,
+    /// Files left out, with the reason.
+    pub skipped: Vec<(String, String)>,
+    /// Files and directories left out once the budget was spent.
+    pub omitted: usize,
+    remaining: usize,
+}
+
+impl Attachments {
+    pub fn new(budget: usize) -> Attachments {
+        Attachments {
+            text: String::new(),
+            files: Vec::new(),
+            skipped: Vec::new(),
+            omitted: 0,
+            remaining: budget,
+        }
+    }
+
+    /// Adds a file, or the text files under a directory minus hidden and
+    /// `.gitignore`d ones.
+    pub fn add(&mut self, path: &str) -> Result<(), String> {
+        let resolved = PathBuf::from(paths::expand_home(path));
+        let metadata = fs::metadata(&resolved).map_err(|e| format!("Cannot attach {path}: {e}"))?;
+        if !metadata.is_dir() {
+            self.add_file(&resolved, path.to_string());
+            return Ok(());
+        }
+        let root = fs::canonicalize(&resolved).map_err(|e| format!("Cannot attach {path}: {e}"))?;
+        let mut ignores = parent_ignores(&root);
+        self.walk(&root, &root, Path::new(path), &mut ignores);
+        Ok(())
+    }
+
+    fn walk(&mut self, dir: &Path, root: &Path, shown: &Path, ignores: &mut Vec<IgnoreFile>) {
+        let pushed = IgnoreFile::load(dir)
+            .map(|file| ignores.push(file))
+            .is_some();
+        let mut entries: Vec<_> = match fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(Result::ok).collect(),
+            Err(e) => {
+                let name = shown.join(dir.strip_prefix(root).unwrap_or(dir));
+                self.skip(name.display().to_string(), e.to_string());
+                Vec::new()
+            }
+        };
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            // Symlinked directories are not followed, so walks cannot loop.
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            if is_ignored(ignores, &path, is_dir) {
+                continue;
+            }
+            if is_dir && self.remaining == 0 {
+                // Not read: nothing under it could be attached.
+                self.omitted += 1;
+            } else if is_dir {
+                self.walk(&path, root, shown, ignores);
+            } else if path.is_file() {
+                let name = shown.join(path.strip_prefix(root).unwrap_or(&path));
+                self.add_file(&path, name.display().to_string());
+            }
+        }
+        if pushed {
+            ignores.pop();
+        }
+    }
+
+    fn add_file(&mut self, path: &Path, name: String) {
+        if self.files.contains(&name) {
+            return;
+        }
+        if self.remaining == 0 {
+            self.omitted += 1;
+            return;
+        }
+        let mut bytes = Vec::new();
+        let size = match fs::File::open(path).and_then(|file| {
+            let size = file.metadata()?.len();
+            file.take(self.remaining as u64).read_to_end(&mut bytes)?;
+            Ok(size)
+        }) {
+            Ok(size) => size,
+            Err(e) => return self.skip(name, e.to_string()),
+        };
+        if bytes[..bytes.len().min(SNIFF_BYTES)].contains(&0) {
+            return self.skip(name, "binary".to_string());
+        }
+
+        let header = if (bytes.len() as u64) < size {
+            // End on a character boundary.
+            if let Err(e) = std::str::from_utf8(&bytes) {
+                if e.error_len().is_none() {
+                    bytes.truncate(e.valid_up_to());
+                }
+            }
+            format!(
+                "File {name} ({size} bytes, truncated to the first {}):",
+                bytes.len()
+            )
+        } else {
+            format!("File {name} ({size} bytes):")
+        };
+        self.remaining = self.remaining.saturating_sub(bytes.len());
+        if !self.text.is_empty() {
+            self.text.push_str("\n\n");
+        }
+        let content = String::from_utf8_lossy(&bytes);
+        self.text
+            .push_str(&format!("{header}\n{}", input::fence(content.trim_end())));
+        self.files.push(name);
+    }
+
+    fn skip(&mut self, name: String, reason: String) {
+        self.skipped.push((name, reason));
+    }
+
+    /// Appends the files to `message`, after a blank line.
+    pub fn append_to(&self, message: &mut String) {
+        if !self.text.is_empty() {
+            message.push_str("\n\n");
+            message.push_str(&self.text);
+        }
+    }
+
+    /// What was attached and skipped, for the user; `None` when nothing was asked for.
+    pub fn summary(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if !self.files.is_empty() {
+            parts.push(format!("attached {}", self.files.join(", ")));
+        }
+        if !self.skipped.is_empty() {
+            let skipped: Vec<String> = self
+                .skipped
+                .iter()
+                .map(|(name, reason)| format!("{name} ({reason})"))
+                .collect();
+            parts.push(format!("skipped {}", skipped.join(", ")));
+        }
+        if self.omitted > 0 {
+            let what = if self.omitted == 1 {
+                "file or directory"
+            } else {
+                "files or directories"
+            };
+            parts.push(format!(
+                "omitted {} more {what} (over the size budget)",
+                self.omitted
+            ));
+        }
+        (!parts.is_empty()).then(|| parts.join("; "))
+    }
+}
+
+/// Paths mentioned as `@path` in a message, for those that exist. Trailing
+/// punctuation is dropped, so `see @Cargo.toml.` finds `Cargo.toml`.
+pub fn mentions(message: &str) -> Vec<String> {
+    message
+        .split_whitespace()
+        .filter_map(|word| word.strip_prefix('@'))
+        .filter_map(|path| {
+            let trimmed =
+                path.trim_end_matches([',', '.', ';', ':', '!', '?', ')', '"', '\'', '`']);
+            [path, trimmed]
+                .into_iter()
+                .find(|p| !p.is_empty() && Path::new(&paths::expand_home(p)).exists())
+                .map(str::to_string)
+        })
+        .collect()
+}
+
+/// One `.gitignore` line.
+#[derive(Debug)]
+struct IgnoreRule {
+    pattern: Pattern,
+    negated: bool,
+    dir_only: bool,
+    /// Patterns with a `/` match the path from the `.gitignore` directory,
+    /// others match the name at any depth.
+    anchored: bool,
+}
+
+impl IgnoreRule {
+    fn parse(line: &str) -> Option<IgnoreRule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let line = line.strip_prefix('\\').unwrap_or(line);
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        Some(IgnoreRule {
+            pattern: Pattern::new(line.trim_start_matches('/')).ok()?,
+            negated,
+            dir_only,
+            anchored: line.contains('/'),
+        })
+    }
+}
+
+/// The rules of one `.gitignore` and the directory they are relative to.
+#[derive(Debug)]
+struct IgnoreFile {
+    base: PathBuf,
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreFile {
+    fn load(dir: &Path) -> Option<IgnoreFile> {
+        let text = fs::read_to_string(dir.join(".gitignore")).ok()?;
+        Some(IgnoreFile {
+            base: dir.to_path_buf(),
+            rules: text.lines().filter_map(IgnoreRule::parse).collect(),
+        })
+    }
+
+    /// Whether the last rule matching `path` ignores it, `None` if none matches.
+    fn verdict(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = path.strip_prefix(&self.base).ok()?.to_string_lossy();
+        let name = path.file_name()?.to_string_lossy();
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| {
+                (is_dir || !rule.dir_only)
+                    && if rule.anchored {
+                        rule.pattern.matches_with(&relative, IGNORE_MATCH)
+                    } else {
+                        rule.pattern.matches_with(&name, IGNORE_MATCH)
+                    }
+            })
+            .map(|rule| !rule.negated)
+    }
+}
+
+/// Deeper `.gitignore` files take precedence over those above them.
+fn is_ignored(ignores: &[IgnoreFile], path: &Path, is_dir: bool) -> bool {
+    ignores
+        .iter()
+        .rev()
+        .find_map(|file| file.verdict(path, is_dir))
+        .unwrap_or(false)
+}
+
+/// `.gitignore` files above `dir` up to the root of its git repository,
+/// outermost first. None outside a repository.
+fn parent_ignores(dir: &Path) -> Vec<IgnoreFile> {
+    let Some(repo) = dir.ancestors().find(|d| d.join(".git").exists()) else {
+        return Vec::new();
+    };
+    let mut ignores: Vec<IgnoreFile> = dir
+        .ancestors()
+        .skip(1)
+        .take_while(|d| d.starts_with(repo))
+        .filter_map(IgnoreFile::load)
+        .collect();
+    ignores.reverse();
+    ignores
+}
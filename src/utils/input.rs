@@ -0,0 +1,108 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::fd::AsFd;
+use std::os::unix::fs::FileTypeExt;
+
+/// Default number of bytes of piped stdin attached to a prompt.
+pub const DEFAULT_INPUT_LIMIT: usize = 32 * 1024;
+
+/// The controlling terminal, open for reading and writing, if there is one.
+fn terminal() -> Option<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .ok()
+}
+
+/// Whether prompts can reach the user even though stdin is taken by a pipe.
+pub fn has_terminal() -> bool {
+    terminal().is_some()
+}
+
+/// Whether stdin is a pipe or a regular file. Terminals, sockets and devices
+/// such as `/dev/null` are not read: they may never reach end of input.
+fn stdin_is_piped() -> bool {
+    io::stdin()
+        .as_fd()
+        .try_clone_to_owned()
+        .and_then(|fd| File::from(fd).metadata())
+        .is_ok_and(|metadata| metadata.file_type().is_fifo() || metadata.is_file())
+}
+
+/// Reads stdin when it is a pipe or a file and returns it as a fenced block
+/// for the prompt, `None` when it is not, when the input is empty or when
+/// `max_bytes` is 0. Only the last `max_bytes` are kept, where logs usually
+/// show what failed.
+pub fn read_piped(max_bytes: usize) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    if max_bytes == 0 || !stdin_is_piped() {
+        return Ok(None);
+    }
+    let stdin = io::stdin();
+    let mut bytes = Vec::new();
+    stdin
+        .lock()
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Failed to read stdin: {e}"))?;
+    if bytes.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+    if bytes.contains(&0) {
+        return Err(format!(
+            "Piped input looks binary ({} bytes); only text can be attached",
+            bytes.len()
+        )
+        .into());
+    }
+
+    let mut start = bytes.len().saturating_sub(max_bytes);
+    // Start on a character boundary.
+    while start < bytes.len() && bytes[start] & 0xC0 == 0x80 {
+        start += 1;
+    }
+    let text = String::from_utf8_lossy(&bytes[start..]);
+    let header = if start > 0 {
+        format!(
+            "Input piped to aic ({} bytes, the first {start} truncated):",
+            bytes.len()
+        )
+    } else {
+        format!("Input piped to aic ({} bytes):", bytes.len())
+    };
+    Ok(Some(format!("{header}\n{}", fence(text.trim_end()))))
+}
+
+/// Wraps `text` in a code fence longer than any run of backticks inside it.
+pub fn fence(text: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{fence}\n{text}\n{fence}")
+}
+
+/// Shows `prompt` and reads one line of answer from the controlling terminal,
+/// so that piped stdin and confirmations can coexist. Without a terminal the
+/// prompt goes to `fallback` and the answer is read from stdin. `None` at end
+/// of input.
+pub fn prompt_line(prompt: &str, fallback: &mut dyn Write) -> Option<String> {
+    let mut answer = String::new();
+    let read = match terminal() {
+        Some(mut tty) => {
+            let _ = write!(tty, "{prompt}");
+            let _ = tty.flush();
+            BufReader::new(tty).read_line(&mut answer)
+        }
+        None => {
+            let _ = write!(fallback, "{prompt}");
+            let _ = fallback.flush();
+            io::stdin().lock().read_line(&mut answer)
+        }
+    };
+    match read {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(answer),
+    }
+}
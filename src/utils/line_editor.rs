@@ -0,0 +1,91 @@
+use crate::core::config;
+use rustyline::config::{Behavior, Config as EditorConfig};
+use rustyline::error::ReadlineError;
+use rustyline::{Cmd, DefaultEditor, KeyCode, KeyEvent, Modifiers};
+use std::path::PathBuf;
+
+/// Entries kept in the history file.
+const HISTORY_SIZE: usize = 1000;
+
+const FENCE: &str = "```";
+
+/// Prompt shown while a fenced block is open.
+const CONTINUATION_PROMPT: &str = "....... ";
+
+/// Reads chat input with line editing, persistent history and reverse search
+/// (Ctrl-R). A line that opens a ``` fence keeps reading until it is closed,
+/// and Alt-Enter inserts a newline without sending.
+pub struct LineEditor {
+    editor: DefaultEditor,
+    history: Option<PathBuf>,
+}
+
+impl LineEditor {
+    /// With `prefer_terminal`, input is read from the terminal even when stdin
+    /// is redirected.
+    pub fn new(prefer_terminal: bool) -> Result<LineEditor, Box<dyn std::error::Error>> {
+        let behavior = if prefer_terminal {
+            Behavior::PreferTerm
+        } else {
+            Behavior::Stdio
+        };
+        let editor_config = EditorConfig::builder()
+            .behavior(behavior)
+            .max_history_size(HISTORY_SIZE)?
+            .history_ignore_dups(true)?
+            .auto_add_history(false)
+            .build();
+        let mut editor = DefaultEditor::with_config(editor_config)?;
+        editor.bind_sequence(KeyEvent(KeyCode::Enter, Modifiers::ALT), Cmd::Newline);
+
+        let history = config::user_data_dir().map(|dir| dir.join("history"));
+        if let Some(path) = &history {
+            // A missing history file just means nothing was typed yet.
+            let _ = editor.load_history(path);
+        }
+        Ok(LineEditor { editor, history })
+    }
+
+    /// Next message, or `None` on Ctrl-D. Ctrl-C discards the current input.
+    pub fn read(&mut self, prompt: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let mut input = loop {
+            match self.editor.readline(prompt) {
+                Ok(line) => break line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        while input.matches(FENCE).count() % 2 == 1 {
+            match self.editor.readline(CONTINUATION_PROMPT) {
+                Ok(line) => {
+                    input.push('\n');
+                    input.push_str(&line);
+                }
+                Err(ReadlineError::Interrupted) => return self.read(prompt),
+                // Ctrl-D closes the block.
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        if !input.trim().is_empty() {
+            self.editor.add_history_entry(input.as_str())?;
+            self.save_history();
+        }
+        Ok(Some(input))
+    }
+
+    fn save_history(&mut self) {
+        let Some(path) = &self.history else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        if let Err(e) = self.editor.save_history(path) {
+            eprintln!("Failed to save history to {}: {e}", path.display());
+        }
+    }
+}
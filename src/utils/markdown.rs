@@ -0,0 +1,631 @@
+use std::io::IsTerminal;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const ITALIC: &str = "\x1b[3m";
+const UNDERLINE: &str = "\x1b[4m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const MAGENTA: &str = "\x1b[35m";
+const CYAN: &str = "\x1b[36m";
+
+/// Renders Markdown for the terminal as it streams in.
+///
+/// Text is rendered line by line: a line is printed once its newline arrives,
+/// tables once their last row is known. Without colors (stdout is not a
+/// terminal, or `NO_COLOR` is set) chunks pass through unchanged.
+pub struct MarkdownRenderer {
+    color: bool,
+    /// Text after the last newline.
+    pending: String,
+    /// Language of the open code block, if any.
+    code: Option<String>,
+    /// Rows of the table being read.
+    table: Vec<String>,
+}
+
+impl MarkdownRenderer {
+    pub fn new() -> MarkdownRenderer {
+        let color = std::io::stdout().is_terminal()
+            && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty());
+        MarkdownRenderer {
+            color,
+            pending: String::new(),
+            code: None,
+            table: Vec::new(),
+        }
+    }
+
+    /// Feeds a chunk and returns what can be printed now.
+    pub fn push(&mut self, chunk: &str) -> String {
+        if !self.color {
+            return chunk.to_string();
+        }
+        self.pending.push_str(chunk);
+        let mut out = String::new();
+        while let Some(end) = self.pending.find('\n') {
+            let line: String = self.pending.drain(..=end).collect();
+            self.render_line(line.trim_end_matches(['\n', '\r']), &mut out);
+        }
+        out
+    }
+
+    /// Renders whatever is still buffered at the end of the answer.
+    pub fn finish(&mut self) -> String {
+        let mut out = String::new();
+        if !self.color {
+            return out;
+        }
+        if !self.pending.is_empty() {
+            let line = std::mem::take(&mut self.pending);
+            self.render_line(&line, &mut out);
+        }
+        self.flush_table(&mut out);
+        self.code = None;
+        out
+    }
+
+    fn render_line(&mut self, line: &str, out: &mut String) {
+        let trimmed = line.trim_start();
+
+        if let Some(lang) = &self.code {
+            if trimmed.starts_with("```") {
+                self.code = None;
+                out.push_str(&format!("{DIM}{line}{RESET}\n"));
+            } else {
+                out.push_str(&highlight(line, lang));
+                out.push('\n');
+            }
+            return;
+        }
+
+        if trimmed.starts_with('|') {
+            self.table.push(trimmed.to_string());
+            return;
+        }
+        self.flush_table(out);
+
+        if let Some(info) = trimmed.strip_prefix("```") {
+            self.code = Some(info.trim().to_lowercase());
+            out.push_str(&format!("{DIM}{line}{RESET}\n"));
+            return;
+        }
+
+        let indent = &line[..line.len() - trimmed.len()];
+        let rendered = if let Some((level, title)) = heading(trimmed) {
+            let style = if level == 1 {
+                format!("{BOLD}{UNDERLINE}")
+            } else {
+                BOLD.to_string()
+            };
+            format!("{style}{}{RESET}", inline(title, &style))
+        } else if is_rule(trimmed) {
+            format!("{DIM}{}{RESET}", "─".repeat(40))
+        } else if let Some(quote) = trimmed.strip_prefix('>') {
+            let style = format!("{DIM}{ITALIC}");
+            format!("{style}│ {}{RESET}", inline(quote.trim_start(), &style))
+        } else if let Some(item) = ["- ", "* ", "+ "]
+            .iter()
+            .find_map(|bullet| trimmed.strip_prefix(bullet))
+        {
+            format!("{CYAN}•{RESET} {}", inline(item, ""))
+        } else if let Some((number, item)) = ordered_item(trimmed) {
+            format!("{CYAN}{number}{RESET} {}", inline(item, ""))
+        } else {
+            inline(trimmed, "")
+        };
+        out.push_str(indent);
+        out.push_str(&rendered);
+        out.push('\n');
+    }
+
+    /// Prints the buffered table with aligned columns.
+    fn flush_table(&mut self, out: &mut String) {
+        if self.table.is_empty() {
+            return;
+        }
+        let rows: Vec<Vec<String>> = std::mem::take(&mut self.table)
+            .iter()
+            .map(|row| split_row(row))
+            .collect();
+        let is_separator = |row: &[String]| {
+            row.iter()
+                .all(|c| !c.is_empty() && c.chars().all(|ch| "-: ".contains(ch)))
+        };
+
+        let has_header = rows.get(1).is_some_and(|row| is_separator(row));
+        let cells: Vec<Vec<String>> = rows
+            .iter()
+            .filter(|row| !is_separator(row))
+            .enumerate()
+            .map(|(i, row)| {
+                let style = if i == 0 && has_header { BOLD } else { "" };
+                row.iter().map(|cell| inline(cell, style)).collect()
+            })
+            .collect();
+        let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|c| {
+                cells
+                    .iter()
+                    .filter_map(|row| row.get(c))
+                    .map(|cell| visible_width(cell))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        for (i, row) in cells.iter().enumerate() {
+            let line: Vec<String> = (0..columns)
+                .map(|c| {
+                    let cell = row.get(c).map(String::as_str).unwrap_or_default();
+                    let pad = widths[c] - visible_width(cell);
+                    if i == 0 && has_header {
+                        format!("{BOLD}{cell}{RESET}{}", " ".repeat(pad))
+                    } else {
+                        format!("{cell}{}", " ".repeat(pad))
+                    }
+                })
+                .collect();
+            out.push_str(&line.join(&format!(" {DIM}│{RESET} ")));
+            out.push('\n');
+            if i == 0 && has_header {
+                let rule: Vec<String> = widths.iter().map(|w| "─".repeat(*w)).collect();
+                out.push_str(&format!("{DIM}{}{RESET}\n", rule.join("─┼─")));
+            }
+        }
+    }
+}
+
+/// Renders a whole answer at once.
+pub fn render(text: &str) -> String {
+    let mut renderer = MarkdownRenderer::new();
+    let mut out = renderer.push(text);
+    out.push_str(&renderer.finish());
+    out
+}
+
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let title = line[level..].strip_prefix(' ')?;
+    (1..=6).contains(&level).then_some((level, title))
+}
+
+fn is_rule(line: &str) -> bool {
+    let compact: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    compact.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|mark| compact.chars().all(|c| c == *mark))
+}
+
+/// `1. item` or `1) item`.
+fn ordered_item(line: &str) -> Option<(&str, &str)> {
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    if digits == 0 {
+        return None;
+    }
+    let rest = &line[digits..];
+    let item = rest
+        .strip_prefix(". ")
+        .or_else(|| rest.strip_prefix(") "))?;
+    Some((&line[..digits + 1], item))
+}
+
+fn split_row(row: &str) -> Vec<String> {
+    let inner = row.trim().trim_start_matches('|');
+    let inner = inner.strip_suffix('|').unwrap_or(inner);
+    inner
+        .split('|')
+        .map(|cell| cell.trim().to_string())
+        .collect()
+}
+
+/// Styles `code`, **bold**, *italic* and [links](url). `base` is the style to
+/// restore after each span.
+fn inline(text: &str, base: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::new();
+    let mut bold = false;
+    let mut italic = false;
+    let mut i = 0;
+
+    let restore = |bold: bool, italic: bool| {
+        let mut style = format!("{RESET}{base}");
+        if bold {
+            style.push_str(BOLD);
+        }
+        if italic {
+            style.push_str(ITALIC);
+        }
+        style
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let prev = if i > 0 { Some(chars[i - 1]) } else { None };
+
+        if c == '\\' && next.is_some_and(|n| n.is_ascii_punctuation()) {
+            out.push(chars[i + 1]);
+            i += 2;
+        } else if c == '`' {
+            match chars[i + 1..].iter().position(|&ch| ch == '`') {
+                Some(len) => {
+                    let code: String = chars[i + 1..i + 1 + len].iter().collect();
+                    out.push_str(&format!("{CYAN}{code}{}", restore(bold, italic)));
+                    i += len + 2;
+                }
+                None => {
+                    out.push(c);
+                    i += 1;
+                }
+            }
+        } else if (c == '*' || c == '_') && next == Some(c) {
+            bold = !bold;
+            out.push_str(&restore(bold, italic));
+            i += 2;
+        } else if italic_marker(c, prev, next, italic) {
+            italic = !italic;
+            out.push_str(&restore(bold, italic));
+            i += 1;
+        } else if c == '[' {
+            match link(&chars[i..]) {
+                Some((label, url, len)) => {
+                    out.push_str(&format!(
+                        "{UNDERLINE}{label}{}{DIM} ({url}){}",
+                        restore(bold, italic),
+                        restore(bold, italic)
+                    ));
+                    i += len;
+                }
+                None => {
+                    out.push(c);
+                    i += 1;
+                }
+            }
+        } else {
+            out.push(c);
+            i += 1;
+        }
+    }
+    if bold || italic {
+        out.push_str(&restore(false, false));
+    }
+    out
+}
+
+/// Whether `c` opens or closes an italic span. `_` only counts at word
+/// boundaries so snake_case names stay intact.
+fn italic_marker(c: char, prev: Option<char>, next: Option<char>, open: bool) -> bool {
+    match c {
+        '*' => open || next.is_some_and(|n| !n.is_whitespace()),
+        '_' if open => next.is_none_or(|n| !n.is_alphanumeric()),
+        '_' => prev.is_none_or(|p| !p.is_alphanumeric()),
+        _ => false,
+    }
+}
+
+/// `[label](url)` at the start of `chars`, with its length.
+fn link(chars: &[char]) -> Option<(String, String, usize)> {
+    let close = chars.iter().position(|&c| c == ']')?;
+    if chars.get(close + 1) != Some(&'(') {
+        return None;
+    }
+    let end = close + 2 + chars[close + 2..].iter().position(|&c| c == ')')?;
+    let label = chars[1..close].iter().collect();
+    let url = chars[close + 2..end].iter().collect();
+    Some((label, url, end + 1))
+}
+
+/// Characters on screen, ignoring escape sequences.
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut in_escape = false;
+    for c in text.chars() {
+        match c {
+            '\x1b' => in_escape = true,
+            'm' if in_escape => in_escape = false,
+            _ if in_escape => {}
+            _ => width += 1,
+        }
+    }
+    width
+}
+
+/// Keywords and line comment marker of the languages worth highlighting.
+fn language(lang: &str) -> Option<(&'static [&'static str], &'static str)> {
+    const RUST: &[&str] = &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+        "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+        "true", "type", "unsafe", "use", "where", "while",
+    ];
+    const PYTHON: &[&str] = &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+        "elif", "else", "except", "False", "finally", "for", "from", "global", "if", "import",
+        "in", "is", "lambda", "None", "not", "or", "pass", "raise", "return", "True", "try",
+        "while", "with", "yield",
+    ];
+    const JS: &[&str] = &[
+        "async",
+        "await",
+        "break",
+        "case",
+        "catch",
+        "class",
+        "const",
+        "continue",
+        "default",
+        "delete",
+        "do",
+        "else",
+        "export",
+        "extends",
+        "false",
+        "finally",
+        "for",
+        "function",
+        "if",
+        "import",
+        "in",
+        "instanceof",
+        "interface",
+        "let",
+        "new",
+        "null",
+        "return",
+        "switch",
+        "this",
+        "throw",
+        "true",
+        "try",
+        "type",
+        "typeof",
+        "undefined",
+        "var",
+        "void",
+        "while",
+        "yield",
+    ];
+    const SHELL: &[&str] = &[
+        "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac",
+        "in", "function", "return", "export", "local", "readonly", "set", "unset",
+    ];
+    const GO: &[&str] = &[
+        "break",
+        "case",
+        "chan",
+        "const",
+        "continue",
+        "default",
+        "defer",
+        "else",
+        "false",
+        "for",
+        "func",
+        "go",
+        "if",
+        "import",
+        "interface",
+        "map",
+        "nil",
+        "package",
+        "range",
+        "return",
+        "select",
+        "struct",
+        "switch",
+        "true",
+        "type",
+        "var",
+    ];
+    const C_LIKE: &[&str] = &[
+        "auto",
+        "bool",
+        "break",
+        "case",
+        "char",
+        "class",
+        "const",
+        "continue",
+        "default",
+        "do",
+        "double",
+        "else",
+        "enum",
+        "extends",
+        "false",
+        "final",
+        "float",
+        "for",
+        "if",
+        "import",
+        "int",
+        "long",
+        "new",
+        "null",
+        "nullptr",
+        "private",
+        "protected",
+        "public",
+        "return",
+        "short",
+        "static",
+        "struct",
+        "switch",
+        "this",
+        "throw",
+        "true",
+        "try",
+        "typedef",
+        "void",
+        "while",
+    ];
+    const SQL: &[&str] = &[
+        "SELECT", "FROM", "WHERE", "INSERT", "INTO", "VALUES", "UPDATE", "SET", "DELETE", "CREATE",
+        "TABLE", "DROP", "ALTER", "JOIN", "LEFT", "RIGHT", "INNER", "ON", "AND", "OR", "NOT",
+        "NULL", "ORDER", "BY", "GROUP", "HAVING", "LIMIT", "AS", "select", "from", "where",
+        "insert", "into", "values", "update", "set", "delete", "create", "table", "join", "on",
+        "and", "or", "not", "null", "order", "by", "group", "limit", "as",
+    ];
+
+    Some(match lang {
+        "rust" | "rs" => (RUST, "//"),
+        "python" | "py" => (PYTHON, "#"),
+        "javascript" | "js" | "typescript" | "ts" | "jsx" | "tsx" => (JS, "//"),
+        "sh" | "bash" | "shell" | "zsh" | "console" => (SHELL, "#"),
+        "go" | "golang" => (GO, "//"),
+        "c" | "cpp" | "c++" | "h" | "java" | "kotlin" | "cs" | "csharp" => (C_LIKE, "//"),
+        "sql" => (SQL, "--"),
+        "toml" | "yaml" | "yml" | "ini" | "dockerfile" | "make" | "makefile" => (&[], "#"),
+        _ => return None,
+    })
+}
+
+/// Colors comments, strings, numbers and keywords of one line of code.
+fn highlight(line: &str, lang: &str) -> String {
+    let Some((keywords, comment)) = language(lang) else {
+        return format!("{GREEN}{line}{RESET}");
+    };
+
+    let chars: Vec<char> = line.chars().collect();
+    let comment: Vec<char> = comment.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if chars[i..].starts_with(&comment) {
+            let rest: String = chars[i..].iter().collect();
+            out.push_str(&format!("{DIM}{rest}{RESET}"));
+            break;
+        } else if c == '"' || c == '\'' || c == '`' {
+            let mut end = i + 1;
+            while end < chars.len() && chars[end] != c {
+                end += if chars[end] == '\\' { 2 } else { 1 };
+            }
+            let end = end.min(chars.len() - 1);
+            let text: String = chars[i..=end].iter().collect();
+            out.push_str(&format!("{YELLOW}{text}{RESET}"));
+            i = end + 1;
+        } else if c.is_ascii_digit() {
+            let end = i + chars[i..]
+                .iter()
+                .take_while(|ch| ch.is_ascii_alphanumeric() || **ch == '.' || **ch == '_')
+                .count();
+            let text: String = chars[i..end].iter().collect();
+            out.push_str(&format!("{MAGENTA}{text}{RESET}"));
+            i = end;
+        } else if c.is_alphabetic() || c == '_' {
+            let end = i + chars[i..]
+                .iter()
+                .take_while(|ch| ch.is_alphanumeric() || **ch == '_')
+                .count();
+            let word: String = chars[i..end].iter().collect();
+            if keywords.contains(&word.as_str()) {
+                out.push_str(&format!("{RED}{word}{RESET}"));
+            } else {
+                out.push_str(&word);
+            }
+            i = end;
+        } else {
+            out.push(c);
+            i += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn renderer(color: bool) -> MarkdownRenderer {
+        MarkdownRenderer {
+            color,
+            pending: String::new(),
+            code: None,
+            table: Vec::new(),
+        }
+    }
+
+    /// Renders `chunks` as if streamed in that order.
+    fn render_chunks(chunks: &[&str]) -> String {
+        let mut renderer = renderer(true);
+        let mut out: String = chunks.iter().map(|chunk| renderer.push(chunk)).collect();
+        out.push_str(&renderer.finish());
+        out
+    }
+
+    #[test]
+    fn plain_output_passes_through_unchanged() {
+        let mut renderer = renderer(false);
+        let text = "# Title\n- **item** with `code`\n```rust\nfn main() {}\n```\n| a |";
+        assert_eq!(renderer.push(text), text);
+        assert_eq!(renderer.finish(), "");
+    }
+
+    #[test]
+    fn lines_are_rendered_once_complete() {
+        let mut renderer = renderer(true);
+        assert_eq!(renderer.push("Hello "), "");
+        assert_eq!(renderer.push("world\nand"), "Hello world\n");
+        assert_eq!(renderer.finish(), "and\n");
+    }
+
+    #[test]
+    fn code_fences_are_highlighted_verbatim() {
+        let out = render_chunks(&["```rust\n", "let x = \"**not bold**\"; // 1\n", "```\n"]);
+        assert_eq!(
+            out,
+            format!(
+                "{DIM}```rust{RESET}\n\
+                 {RED}let{RESET} x = {YELLOW}\"**not bold**\"{RESET}; {DIM}// 1{RESET}\n\
+                 {DIM}```{RESET}\n"
+            )
+        );
+        // Unknown languages are shown in one color.
+        assert_eq!(
+            render_chunks(&["```\n- *x*\n```\n"]),
+            format!("{DIM}```{RESET}\n{GREEN}- *x*{RESET}\n{DIM}```{RESET}\n")
+        );
+    }
+
+    #[test]
+    fn lists_get_bullets_and_numbers() {
+        assert_eq!(
+            render_chunks(&["- one\n  * two\n3. three\n"]),
+            format!("{CYAN}•{RESET} one\n  {CYAN}•{RESET} two\n{CYAN}3.{RESET} three\n")
+        );
+    }
+
+    #[test]
+    fn inline_spans_are_styled() {
+        assert_eq!(inline("run `ls -a`", ""), format!("run {CYAN}ls -a{RESET}"));
+        assert_eq!(
+            inline("**bold** and *it*", ""),
+            format!("{RESET}{BOLD}bold{RESET} and {RESET}{ITALIC}it{RESET}")
+        );
+        assert_eq!(inline("snake_case_name", ""), "snake_case_name");
+        assert_eq!(inline("\\*literal\\*", ""), "*literal*");
+        assert_eq!(
+            inline("[docs](https://x.dev)", ""),
+            format!("{UNDERLINE}docs{RESET}{DIM} (https://x.dev){RESET}")
+        );
+    }
+
+    #[test]
+    fn headings_and_tables() {
+        assert_eq!(
+            render_chunks(&["## Notes\n"]),
+            format!("{BOLD}Notes{RESET}\n")
+        );
+        let out = render_chunks(&["| a | bb |\n|---|---|\n| ccc | d |\n"]);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(visible_width(lines[0]), visible_width(lines[2]));
+        assert!(lines[1].contains("────┼───"), "{out:?}");
+    }
+}
@@ -1 +1,8 @@
-pub mod security; 
+pub mod attach;
+pub mod input;
+pub mod line_editor;
+pub mod markdown;
+pub mod paths;
+pub mod policy;
+pub mod security;
+pub mod shell;
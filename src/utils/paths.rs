@@ -0,0 +1,72 @@
+use std::path::{Component, Path, PathBuf};
+
+/// Replaces a leading `~`, `$HOME` or `${HOME}` with the home directory.
+pub fn expand_home(path: &str) -> String {
+    let Ok(home) = std::env::var("HOME") else {
+        return path.to_string();
+    };
+    for prefix in ["~", "$HOME", "${HOME}"] {
+        if let Some(rest) = path.strip_prefix(prefix) {
+            if rest.is_empty() || rest.starts_with('/') {
+                return format!("{home}{rest}");
+            }
+        }
+    }
+    path.to_string()
+}
+
+/// `path` made absolute against `cwd` with `.` and `..` removed, without
+/// touching the filesystem.
+pub fn resolve(path: &str, cwd: &Path) -> PathBuf {
+    let path = PathBuf::from(expand_home(path));
+    let joined = if path.is_relative() {
+        cwd.join(path)
+    } else {
+        path
+    };
+    let mut normalized = PathBuf::new();
+    for part in joined.components() {
+        match part {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// The closest ancestor of `cwd` holding a `.git`, or `cwd` itself.
+pub fn project_root(cwd: &Path) -> PathBuf {
+    cwd.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .unwrap_or(cwd)
+        .to_path_buf()
+}
+
+/// Whether `path` is under a scratch directory where anything goes.
+pub fn is_temp(path: &Path) -> bool {
+    let tmpdir = std::env::var_os("TMPDIR").map(PathBuf::from);
+    path.starts_with("/tmp")
+        || path.starts_with("/var/tmp")
+        || tmpdir.is_some_and(|dir| path.starts_with(dir))
+}
+
+/// A file or directory directly under `$HOME` whose name starts with a dot,
+/// or anything inside one, e.g. `~/.bashrc` or `~/.ssh/config`.
+pub fn home_dotfile(path: &Path) -> Option<String> {
+    let home = std::env::var_os("HOME")?;
+    let rest = path.strip_prefix(&home).ok()?;
+    let first = rest.components().next()?;
+    first
+        .as_os_str()
+        .to_string_lossy()
+        .starts_with('.')
+        .then(|| format!("~/{}", rest.display()))
+}
+
+/// Whether `path` contains shell glob characters.
+pub fn is_glob(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
@@ -0,0 +1,223 @@
+use crate::utils::paths;
+use glob::Pattern;
+use regex::Regex;
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// What a matching rule does. When several rules match the same command,
+/// `deny` wins over `confirm`, which wins over `allow`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    /// Run without asking and skip the built-in analysis of the program.
+    /// The commands it runs, e.g. through `find -exec`, are still checked.
+    Allow,
+    /// Always ask, even with `-y`.
+    Confirm,
+    /// Never run, even with `-y`.
+    Deny,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Allow => write!(f, "allow"),
+            Action::Confirm => write!(f, "confirm"),
+            Action::Deny => write!(f, "deny"),
+        }
+    }
+}
+
+/// Shape of a policy file:
+///
+/// ```toml
+/// [[deny]]
+/// program = "terraform"
+/// args = ["destroy"]
+/// reason = "run it from CI"
+///
+/// [[allow]]
+/// program = "cargo"
+/// args = ["test"]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    allow: Vec<RuleFile>,
+    #[serde(default)]
+    confirm: Vec<RuleFile>,
+    #[serde(default)]
+    deny: Vec<RuleFile>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    program: Option<String>,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    paths: Vec<String>,
+    regex: Option<String>,
+    reason: Option<String>,
+}
+
+/// One rule. Every condition it sets must hold for it to match.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub action: Action,
+    /// Glob on the program name, e.g. `terraform` or `docker*`.
+    program: Option<Pattern>,
+    /// Globs that must each match at least one argument.
+    args: Vec<Pattern>,
+    /// Globs of which one must match a path operand or redirection target.
+    paths: Vec<Pattern>,
+    /// Regex searched in the simple command, e.g. `rm .*-rf`.
+    regex: Option<Regex>,
+    pub reason: Option<String>,
+    /// File and position the rule was read from, e.g. `policy.toml: deny[1]`.
+    pub origin: String,
+}
+
+/// What a simple command looks like to the rules.
+pub struct Invocation<'a> {
+    pub program: &'a str,
+    pub args: &'a [String],
+    /// Path operands and redirection targets.
+    pub paths: Vec<&'a str>,
+    /// The simple command as written.
+    pub text: &'a str,
+    /// Directory relative paths are resolved against.
+    pub cwd: &'a Path,
+}
+
+impl Rule {
+    fn matches(&self, invocation: &Invocation) -> bool {
+        self.program
+            .as_ref()
+            .is_none_or(|p| p.matches(invocation.program))
+            && self
+                .args
+                .iter()
+                .all(|p| invocation.args.iter().any(|a| p.matches(a)))
+            && (self.paths.is_empty()
+                || self.paths.iter().any(|p| {
+                    invocation.paths.iter().any(|path| {
+                        p.matches(path) || p.matches_path(&paths::resolve(path, invocation.cwd))
+                    })
+                }))
+            && self
+                .regex
+                .as_ref()
+                .is_none_or(|r| r.is_match(invocation.text))
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.action, self.origin)?;
+        if let Some(reason) = &self.reason {
+            write!(f, ": {reason}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Rules from every policy file, in load order.
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    pub rules: Vec<Rule>,
+    pub files: Vec<PathBuf>,
+}
+
+impl Policy {
+    /// Reads and appends the rules of `path`.
+    pub fn load_file(&mut self, path: &Path) -> Result<(), String> {
+        self.load(path, true)
+    }
+
+    /// Like [`Policy::load_file`] for a project policy, which comes with the
+    /// code it guards and so may only add `confirm` and `deny` rules.
+    pub fn load_project_file(&mut self, path: &Path) -> Result<(), String> {
+        self.load(path, false)
+    }
+
+    fn load(&mut self, path: &Path, trusted: bool) -> Result<(), String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        let file: PolicyFile =
+            toml::from_str(&text).map_err(|e| format!("Invalid policy {}: {e}", path.display()))?;
+        if !trusted && !file.allow.is_empty() {
+            return Err(format!(
+                "{}: allow rules are only read from the user policy file",
+                path.display()
+            ));
+        }
+
+        let sections = [
+            (Action::Allow, "allow", file.allow),
+            (Action::Confirm, "confirm", file.confirm),
+            (Action::Deny, "deny", file.deny),
+        ];
+        for (action, name, rules) in sections {
+            for (index, rule) in rules.into_iter().enumerate() {
+                let origin = format!("{}: {name}[{index}]", path.display());
+                self.rules.push(compile(action, rule, origin)?);
+            }
+        }
+        self.files.push(path.to_path_buf());
+        Ok(())
+    }
+
+    /// The rule deciding `invocation`: the first `deny` that matches, else the
+    /// first `confirm`, else the first `allow`.
+    pub fn evaluate(&self, invocation: &Invocation) -> Option<&Rule> {
+        let mut best: Option<&Rule> = None;
+        for rule in self.rules.iter().filter(|r| r.matches(invocation)) {
+            if best.is_none_or(|b| rule.action > b.action) {
+                best = Some(rule);
+            }
+        }
+        best
+    }
+}
+
+fn compile(action: Action, rule: RuleFile, origin: String) -> Result<Rule, String> {
+    let glob = |pattern: &str| {
+        Pattern::new(&paths::expand_home(pattern))
+            .map_err(|e| format!("{origin}: invalid glob {pattern:?}: {e}"))
+    };
+
+    if rule.program.is_none()
+        && rule.args.is_empty()
+        && rule.paths.is_empty()
+        && rule.regex.is_none()
+    {
+        return Err(format!(
+            "{origin}: a rule needs at least one of program, args, paths or regex"
+        ));
+    }
+
+    Ok(Rule {
+        action,
+        program: rule.program.as_deref().map(glob).transpose()?,
+        args: rule
+            .args
+            .iter()
+            .map(|p| glob(p))
+            .collect::<Result<_, _>>()?,
+        paths: rule
+            .paths
+            .iter()
+            .map(|p| glob(p))
+            .collect::<Result<_, _>>()?,
+        regex: rule
+            .regex
+            .as_deref()
+            .map(|r| Regex::new(r).map_err(|e| format!("{origin}: invalid regex {r:?}: {e}")))
+            .transpose()?,
+        reason: rule.reason,
+        origin,
+    })
+}
@@ -0,0 +1,635 @@
+//! A small POSIX shell parser used to analyze commands before they run.
+//!
+//! Nothing is expanded or executed: the input is split into simple commands
+//! (pipelines, lists, subshells and groups are flattened in source order) and the
+//! bodies of `$(...)`, backtick and `<(...)` substitutions are parsed as well, so
+//! every program the shell would start shows up in the result.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectOp {
+    /// `<`
+    Read,
+    /// `>`, `>|`, `&>`
+    Write,
+    /// `>>`, `&>>`
+    Append,
+    /// `<>`
+    ReadWrite,
+    /// `>&`, `<&`
+    Duplicate,
+    /// `<<`, `<<-`
+    HereDoc,
+    /// `<<<`
+    HereString,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirect {
+    pub fd: Option<u32>,
+    pub op: RedirectOp,
+    pub target: String,
+}
+
+impl Redirect {
+    /// Whether the redirection may create or modify its target file. `>&word`
+    /// duplicates a descriptor only when `word` is a number or `-`; otherwise
+    /// it writes stdout and stderr to the file, like `&>`.
+    pub fn writes(&self) -> bool {
+        match self.op {
+            RedirectOp::Write | RedirectOp::Append | RedirectOp::ReadWrite => true,
+            RedirectOp::Duplicate => {
+                self.target != "-" && !self.target.chars().all(|c| c.is_ascii_digit())
+            }
+            _ => false,
+        }
+    }
+}
+
+/// One program invocation with its arguments, after quote removal.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SimpleCommand {
+    /// Leading `NAME=value` words.
+    pub assignments: Vec<String>,
+    pub words: Vec<String>,
+    pub redirects: Vec<Redirect>,
+    /// Reads the stdout of the previous command through a pipe.
+    pub piped: bool,
+}
+
+impl SimpleCommand {
+    pub fn program(&self) -> Option<&str> {
+        self.words.first().map(String::as_str)
+    }
+}
+
+impl fmt::Display for SimpleCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words = self.assignments.iter().chain(&self.words);
+        let mut first = true;
+        for word in words {
+            if !first {
+                write!(f, " ")?;
+            }
+            first = false;
+            write!(f, "{}", quote(word))?;
+        }
+        for redirect in &self.redirects {
+            let op = match redirect.op {
+                RedirectOp::Read => "<",
+                RedirectOp::Write => ">",
+                RedirectOp::Append => ">>",
+                RedirectOp::ReadWrite => "<>",
+                RedirectOp::Duplicate => ">&",
+                RedirectOp::HereDoc => "<<",
+                RedirectOp::HereString => "<<<",
+            };
+            let fd = redirect.fd.map(|fd| fd.to_string()).unwrap_or_default();
+            let sep = if first { "" } else { " " };
+            first = false;
+            write!(f, "{sep}{fd}{op} {}", quote(&redirect.target))?;
+        }
+        Ok(())
+    }
+}
+
+/// Quotes `word` for display when it contains characters the shell would split on.
+pub fn quote(word: &str) -> String {
+    let plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%^~*?[]{}$".contains(c));
+    if plain {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+/// Splits a command line into the simple commands it runs, in execution order.
+pub fn parse(input: &str) -> Vec<SimpleCommand> {
+    let tokens = Lexer::new(input).run();
+    let mut commands = Vec::new();
+    let mut current = SimpleCommand::default();
+    let mut pending_redirect: Option<(Option<u32>, RedirectOp)> = None;
+
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Word {
+                text,
+                substitutions,
+            } => {
+                // Substitutions run before the command that contains them.
+                for body in substitutions {
+                    commands.extend(parse(&body));
+                }
+                if let Some((fd, op)) = pending_redirect.take() {
+                    current.redirects.push(Redirect {
+                        fd,
+                        op,
+                        target: text,
+                    });
+                } else if current.words.is_empty() && is_assignment(&text) {
+                    current.assignments.push(text);
+                } else if current.words.is_empty() && RESERVED_WORDS.contains(&text.as_str()) {
+                    // `if`, `then`, `{`, ... only structure the script
+                } else {
+                    current.words.push(text);
+                }
+            }
+            Token::Redirect(fd, op) => pending_redirect = Some((fd, op)),
+            Token::Op(op) => {
+                // `name() { ...; }` defines a function, it does not run `name`.
+                if op == "(" && current.words.len() == 1 && tokens.peek() == Some(&Token::Op(")")) {
+                    tokens.next();
+                    current = SimpleCommand::default();
+                    continue;
+                }
+                let piped = op == "|";
+                finish(&mut commands, &mut current, piped);
+            }
+        }
+    }
+    finish(&mut commands, &mut current, false);
+    commands
+}
+
+const RESERVED_WORDS: &[&str] = &[
+    "if", "then", "else", "elif", "fi", "do", "done", "while", "until", "!", "{", "}", "esac",
+];
+
+fn finish(commands: &mut Vec<SimpleCommand>, current: &mut SimpleCommand, next_piped: bool) {
+    let command = std::mem::take(current);
+    if !command.words.is_empty() || !command.redirects.is_empty() || !command.assignments.is_empty()
+    {
+        commands.push(command);
+    }
+    current.piped = next_piped;
+}
+
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word {
+        text: String,
+        /// Bodies of `$(...)`, backtick and process substitutions in this word.
+        substitutions: Vec<String>,
+    },
+    Op(&'static str),
+    Redirect(Option<u32>, RedirectOp),
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    tokens: Vec<Token>,
+    /// Here-document delimiters whose bodies start after the next newline.
+    heredocs: Vec<(String, bool)>,
+    /// Set right after `<<`/`<<-` so the next word is taken as a delimiter.
+    heredoc_strip_tabs: Option<bool>,
+}
+
+impl Lexer {
+    fn new(input: &str) -> Self {
+        Lexer {
+            chars: input.chars().collect(),
+            pos: 0,
+            tokens: Vec::new(),
+            heredocs: Vec::new(),
+            heredoc_strip_tabs: None,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(i, c)| self.peek_at(i) == Some(c))
+    }
+
+    fn op(&mut self, op: &'static str) {
+        self.pos += op.chars().count();
+        self.tokens.push(Token::Op(op));
+    }
+
+    fn redirect(&mut self, fd: Option<u32>, text: &str, op: RedirectOp) {
+        self.pos += text.chars().count();
+        if op == RedirectOp::HereDoc {
+            self.heredoc_strip_tabs = Some(text == "<<-");
+        }
+        self.tokens.push(Token::Redirect(fd, op));
+    }
+
+    fn run(mut self) -> Vec<Token> {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' => self.pos += 1,
+                '\n' => {
+                    self.op(";");
+                    self.skip_heredoc_bodies();
+                }
+                '#' => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                '&' if self.starts_with("&&") => self.op("&&"),
+                '&' if self.starts_with("&>>") => self.redirect(None, "&>>", RedirectOp::Append),
+                '&' if self.starts_with("&>") => self.redirect(None, "&>", RedirectOp::Write),
+                '&' => self.op("&"),
+                '|' if self.starts_with("||") => self.op("||"),
+                '|' if self.starts_with("|&") => {
+                    self.pos += 1;
+                    self.op("|");
+                }
+                '|' => self.op("|"),
+                ';' if self.starts_with(";;") => self.op(";;"),
+                ';' => self.op(";"),
+                '(' => self.op("("),
+                ')' => self.op(")"),
+                '<' | '>' if self.peek_at(1) == Some('(') => self.word(),
+                '<' | '>' => self.redirect_op(None),
+                _ => self.word(),
+            }
+        }
+        self.tokens
+    }
+
+    fn redirect_op(&mut self, fd: Option<u32>) {
+        const OPS: &[(&str, RedirectOp)] = &[
+            ("<<<", RedirectOp::HereString),
+            ("<<-", RedirectOp::HereDoc),
+            ("<<", RedirectOp::HereDoc),
+            ("<&", RedirectOp::Duplicate),
+            ("<>", RedirectOp::ReadWrite),
+            ("<", RedirectOp::Read),
+            (">>", RedirectOp::Append),
+            (">&", RedirectOp::Duplicate),
+            (">|", RedirectOp::Write),
+            (">", RedirectOp::Write),
+        ];
+        for (text, op) in OPS {
+            if self.starts_with(text) {
+                self.redirect(fd, text, *op);
+                return;
+            }
+        }
+    }
+
+    fn word(&mut self) {
+        let mut text = String::new();
+        let mut substitutions = Vec::new();
+        let mut quoted = false;
+
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\n' | ';' | '&' | '|' | '(' | ')' => break,
+                '<' | '>' if self.peek_at(1) == Some('(') => {
+                    self.pos += 2;
+                    let body = self.balanced(')');
+                    text.push(c);
+                    text.push('(');
+                    text.push_str(&body);
+                    text.push(')');
+                    substitutions.push(body);
+                }
+                '<' | '>' => {
+                    // `2>file`: a word made only of digits is the redirected fd
+                    if !quoted && !text.is_empty() && text.chars().all(|c| c.is_ascii_digit()) {
+                        let fd = text.parse().ok();
+                        self.redirect_op(fd);
+                        return;
+                    }
+                    break;
+                }
+                '\\' => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some('\n') => self.pos += 1,
+                        Some(escaped) => {
+                            text.push(escaped);
+                            self.pos += 1;
+                        }
+                        None => {}
+                    }
+                    quoted = true;
+                }
+                '\'' => {
+                    self.pos += 1;
+                    while let Some(c) = self.peek() {
+                        self.pos += 1;
+                        if c == '\'' {
+                            break;
+                        }
+                        text.push(c);
+                    }
+                    quoted = true;
+                }
+                '"' => {
+                    self.pos += 1;
+                    self.double_quoted(&mut text, &mut substitutions);
+                    quoted = true;
+                }
+                '`' => {
+                    self.pos += 1;
+                    let body = self.backtick();
+                    text.push('`');
+                    text.push_str(&body);
+                    text.push('`');
+                    substitutions.push(body);
+                }
+                '$' => self.dollar(&mut text, &mut substitutions),
+                _ => {
+                    text.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+
+        if let Some(strip_tabs) = self.heredoc_strip_tabs.take() {
+            self.heredocs.push((text.clone(), strip_tabs));
+        }
+        self.tokens.push(Token::Word {
+            text,
+            substitutions,
+        });
+    }
+
+    fn double_quoted(&mut self, text: &mut String, substitutions: &mut Vec<String>) {
+        while let Some(c) = self.peek() {
+            match c {
+                '"' => {
+                    self.pos += 1;
+                    return;
+                }
+                '\\' => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some('\n') => self.pos += 1,
+                        Some(escaped @ ('$' | '`' | '"' | '\\')) => {
+                            text.push(escaped);
+                            self.pos += 1;
+                        }
+                        _ => text.push('\\'),
+                    }
+                }
+                '`' => {
+                    self.pos += 1;
+                    let body = self.backtick();
+                    text.push('`');
+                    text.push_str(&body);
+                    text.push('`');
+                    substitutions.push(body);
+                }
+                '$' => self.dollar(text, substitutions),
+                _ => {
+                    text.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    /// Handles `$(...)`, `$((...))`, `${...}` and plain `$` at the current position.
+    fn dollar(&mut self, text: &mut String, substitutions: &mut Vec<String>) {
+        if self.starts_with("$((") {
+            self.pos += 3;
+            let body = self.balanced(')');
+            // the arithmetic expansion's second closing paren
+            if self.peek() == Some(')') {
+                self.pos += 1;
+            }
+            text.push_str("$((");
+            text.push_str(&body);
+            text.push_str("))");
+        } else if self.starts_with("$(") {
+            self.pos += 2;
+            let body = self.balanced(')');
+            text.push_str("$(");
+            text.push_str(&body);
+            text.push(')');
+            substitutions.push(body);
+        } else if self.starts_with("${") {
+            self.pos += 2;
+            let body = self.balanced('}');
+            text.push_str("${");
+            text.push_str(&body);
+            text.push('}');
+        } else {
+            text.push('$');
+            self.pos += 1;
+        }
+    }
+
+    /// Reads up to the `close` that balances an already consumed opener and
+    /// returns the text in between, skipping over quoted sections.
+    fn balanced(&mut self, close: char) -> String {
+        let open = if close == ')' { '(' } else { '{' };
+        let start = self.pos;
+        let mut depth = 1;
+        while let Some(c) = self.peek() {
+            match c {
+                '\\' => self.pos += 1,
+                '\'' => {
+                    self.pos += 1;
+                    while self.peek().is_some_and(|c| c != '\'') {
+                        self.pos += 1;
+                    }
+                }
+                '"' => {
+                    self.pos += 1;
+                    while let Some(c) = self.peek() {
+                        if c == '\\' {
+                            self.pos += 1;
+                        } else if c == '"' {
+                            break;
+                        }
+                        self.pos += 1;
+                    }
+                }
+                c if c == open => depth += 1,
+                c if c == close => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let body: String = self.chars[start..self.pos].iter().collect();
+                        self.pos += 1;
+                        return body;
+                    }
+                }
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        self.chars[start.min(self.chars.len())..].iter().collect()
+    }
+
+    fn backtick(&mut self) -> String {
+        let mut body = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '`' => break,
+                '\\' => {
+                    if let Some(escaped) = self.peek() {
+                        if !matches!(escaped, '`' | '\\' | '$') {
+                            body.push('\\');
+                        }
+                        body.push(escaped);
+                        self.pos += 1;
+                    }
+                }
+                _ => body.push(c),
+            }
+        }
+        body
+    }
+
+    /// Skips the bodies of here-documents opened on the line that just ended.
+    fn skip_heredoc_bodies(&mut self) {
+        for (delimiter, strip_tabs) in std::mem::take(&mut self.heredocs) {
+            while self.pos < self.chars.len() {
+                let line_end = self.chars[self.pos..]
+                    .iter()
+                    .position(|&c| c == '\n')
+                    .map(|i| self.pos + i)
+                    .unwrap_or(self.chars.len());
+                let line: String = self.chars[self.pos..line_end].iter().collect();
+                self.pos = (line_end + 1).min(self.chars.len());
+                let line = if strip_tabs {
+                    line.trim_start_matches('\t')
+                } else {
+                    &line
+                };
+                if line == delimiter {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(input: &str) -> Vec<Vec<String>> {
+        parse(input).into_iter().map(|cmd| cmd.words).collect()
+    }
+
+    #[test]
+    fn quotes_are_removed_and_keep_words_together() {
+        assert_eq!(
+            words(r#"echo 'a b' "c $d" e\ f 'it'\''s'"#),
+            [["echo", "a b", "c $d", "e f", "it's"]]
+        );
+        assert_eq!(words("echo 'a;b' \"c|d\""), [["echo", "a;b", "c|d"]]);
+    }
+
+    #[test]
+    fn substitutions_run_before_their_command() {
+        assert_eq!(
+            words("echo $(rm -rf build) `id -u` \"$(whoami)\""),
+            [
+                vec!["rm", "-rf", "build"],
+                vec!["id", "-u"],
+                vec!["whoami"],
+                vec!["echo", "$(rm -rf build)", "`id -u`", "$(whoami)"],
+            ]
+        );
+        assert_eq!(
+            words("diff <(sort a) <(sort b)"),
+            [
+                vec!["sort", "a"],
+                vec!["sort", "b"],
+                vec!["diff", "<(sort a)", "<(sort b)"],
+            ]
+        );
+        // Arithmetic and parameter expansions run nothing.
+        assert_eq!(
+            words("echo $((1 + 2)) ${HOME}"),
+            [["echo", "$((1 + 2))", "${HOME}"]]
+        );
+    }
+
+    #[test]
+    fn redirects_are_split_from_words() {
+        let commands = parse("cat <in >out 2>>log 2>&1 >| forced");
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].words, ["cat"]);
+        let redirects: Vec<_> = commands[0]
+            .redirects
+            .iter()
+            .map(|r| (r.fd, r.op, r.target.as_str()))
+            .collect();
+        assert_eq!(
+            redirects,
+            [
+                (None, RedirectOp::Read, "in"),
+                (None, RedirectOp::Write, "out"),
+                (Some(2), RedirectOp::Append, "log"),
+                (Some(2), RedirectOp::Duplicate, "1"),
+                (None, RedirectOp::Write, "forced"),
+            ]
+        );
+        for (command, writes) in [
+            ("ls &> all", true),
+            ("ls >& all", true),
+            ("ls >&2", false),
+            ("ls 2>&1", false),
+            ("ls >&-", false),
+        ] {
+            let commands = parse(command);
+            assert_eq!(commands[0].redirects[0].writes(), writes, "{command}");
+        }
+    }
+
+    #[test]
+    fn heredoc_bodies_are_not_commands() {
+        assert_eq!(
+            words("cat <<EOF > out\nrm -rf /\nEOF\necho done"),
+            [vec!["cat"], vec!["echo", "done"]]
+        );
+    }
+
+    #[test]
+    fn pipelines_and_lists_are_flattened_in_order() {
+        let commands = parse("a | b && (c; d) || { e & } ; f |& g");
+        let programs: Vec<_> = commands.iter().map(|c| c.program().unwrap()).collect();
+        assert_eq!(programs, ["a", "b", "c", "d", "e", "f", "g"]);
+        let piped: Vec<_> = commands.iter().map(|c| c.piped).collect();
+        assert_eq!(piped, [false, true, false, false, false, false, true]);
+    }
+
+    #[test]
+    fn structure_and_assignments_are_not_programs() {
+        let commands = parse("if true; then FOO=1 make; fi; f() { ls; }");
+        let programs: Vec<_> = commands.iter().map(|c| c.program().unwrap()).collect();
+        assert_eq!(programs, ["true", "make", "ls"]);
+        assert_eq!(commands[1].assignments, ["FOO=1"]);
+    }
+
+    #[test]
+    fn display_quotes_words_that_need_it() {
+        let command = &parse("echo 'a b' > 'my file'")[0];
+        assert_eq!(command.to_string(), "echo 'a b' > 'my file'");
+    }
+}
@@ -0,0 +1,118 @@
+//! `--file` and `@path` attach files to the prompt. The requests are
+//! recorded to check what the model received.
+
+mod common;
+
+use common::{output, text, Sandbox};
+use std::fs;
+
+fn write(sandbox: &Sandbox, path: &str, content: &[u8]) {
+    let path = sandbox.dir.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+#[test]
+fn directories_respect_gitignore_and_skip_binary_files() {
+    let sandbox = Sandbox::new("attach-dir");
+    write(
+        &sandbox,
+        "project/.gitignore",
+        b"target/\n*.log\n!keep.log\n",
+    );
+    write(&sandbox, "project/src/main.rs", b"fn main() {}\n");
+    write(&sandbox, "project/target/out.txt", b"build output\n");
+    write(&sandbox, "project/debug.log", b"noise\n");
+    write(&sandbox, "project/keep.log", b"kept log\n");
+    write(&sandbox, "project/.env", b"SECRET=1\n");
+    write(&sandbox, "project/logo.png", b"\x89PNG\0\0\0");
+
+    let command = sandbox.recorded("attach.toml", &["ask", "review", "--file", "project"]);
+    let output = output(command, "");
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+    assert!(stdout.contains("Read them."), "{stdout}\n{stderr}");
+    assert!(stderr.contains("logo.png (binary)"), "{stderr}");
+
+    let prompt = sandbox.sent(1);
+    assert!(
+        prompt.contains("File project/src/main.rs (13 bytes)"),
+        "{prompt}"
+    );
+    assert!(prompt.contains("fn main() {}"));
+    assert!(prompt.contains("kept log"));
+    assert!(!prompt.contains("build output"), "{prompt}");
+    assert!(!prompt.contains("noise"));
+    assert!(!prompt.contains("SECRET"));
+}
+
+#[test]
+fn the_size_budget_truncates_then_skips() {
+    let sandbox = Sandbox::new("attach-budget");
+    write(&sandbox, "a.txt", b"0123456789abcdefghij\n");
+    write(&sandbox, "b.txt", b"second file\n");
+
+    let mut command = sandbox.recorded(
+        "attach.toml",
+        &["ask", "compare", "--file", "a.txt", "--file", "b.txt"],
+    );
+    command.env("AIC_MAX_ATTACH", "10");
+    let stderr = text(&output(command, "").stderr);
+    assert!(
+        stderr.contains("omitted 1 more file or directory (over the size budget)"),
+        "{stderr}"
+    );
+
+    let prompt = sandbox.sent(1);
+    assert!(
+        prompt.contains("File a.txt (21 bytes, truncated to the first 10)"),
+        "{prompt}"
+    );
+    assert!(!prompt.contains("second file"));
+}
+
+#[test]
+fn directories_are_not_read_once_the_budget_is_spent() {
+    let sandbox = Sandbox::new("attach-budget-dir");
+    write(&sandbox, "project/a.txt", b"0123456789abcdefghij\n");
+    write(&sandbox, "project/b.txt", b"second file\n");
+    write(&sandbox, "project/deep/c.txt", b"third file\n");
+    write(&sandbox, "project/deep/d.txt", b"fourth file\n");
+
+    let mut command = sandbox.recorded("attach.toml", &["ask", "compare", "--file", "project"]);
+    command.env("AIC_MAX_ATTACH", "10");
+    let stderr = text(&output(command, "").stderr);
+    // b.txt and deep/, whose files are not counted one by one.
+    assert!(
+        stderr.contains("omitted 2 more files or directories (over the size budget)"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("c.txt"), "{stderr}");
+}
+
+#[test]
+fn chat_attaches_mentioned_paths() {
+    let sandbox = Sandbox::new("attach-chat");
+    write(&sandbox, "notes.txt", b"remember the milk\n");
+    write(&sandbox, "todo.md", b"- ship it\n");
+
+    let command = sandbox.recorded("attach.toml", &["chat", "--file", "todo.md"]);
+    let input = "what is planned?\nask @someone about @notes.txt.\nexit\n";
+    let output = output(command, input);
+    let stdout = text(&output.stdout);
+    assert!(stdout.contains("Read it too."), "{stdout}");
+
+    let first = sandbox.sent(1);
+    assert!(first.contains("File todo.md (10 bytes)"), "{first}");
+    let second = sandbox.sent(2);
+    assert!(second.contains("File notes.txt"), "{second}");
+    assert!(second.contains("remember the milk"));
+    assert!(!second.contains("todo.md"), "{second}");
+}
+
+#[test]
+fn a_missing_file_is_an_error() {
+    let sandbox = Sandbox::new("attach-missing");
+    let command = sandbox.recorded("attach.toml", &["ask", "read it", "--file", "nowhere.txt"]);
+    let stderr = text(&output(command, "").stderr);
+    assert!(stderr.contains("Cannot attach nowhere.txt"), "{stderr}");
+}
@@ -0,0 +1,282 @@
+//! Helpers shared by the integration tests: each test runs the aic binary
+//! with HOME and the XDG directories pointed at a scratch directory.
+
+// Each test binary uses a different subset of the helpers.
+#![allow(dead_code)]
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+
+/// Scratch directory standing in for HOME, removed when dropped.
+pub struct Sandbox {
+    pub dir: PathBuf,
+}
+
+impl Sandbox {
+    pub fn new(name: &str) -> Sandbox {
+        let dir = std::env::temp_dir().join(format!("aic-test-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Sandbox { dir }
+    }
+
+    /// Runs aic with `fixture` as the model, feeding `stdin`.
+    pub fn run(&self, fixture: &str, args: &[&str], stdin: &str) -> Output {
+        let mut command = self.command(args);
+        command
+            .arg("--model")
+            .arg(format!("mock:{}", fixture_path(fixture).display()));
+        output(command, stdin)
+    }
+
+    /// Runs aic with `fixture` as the model on a terminal where `typed` is
+    /// entered, feeding `stdin`. Returns what was shown on the terminal too.
+    pub fn run_on_terminal(
+        &self,
+        fixture: &str,
+        args: &[&str],
+        typed: &str,
+        stdin: &str,
+    ) -> (Output, String) {
+        let mut command = self.command(args);
+        command
+            .arg("--model")
+            .arg(format!("mock:{}", fixture_path(fixture).display()));
+        on_terminal(command, typed, stdin)
+    }
+
+    /// Runs aic like `recorded` on a terminal, typing each of `keys` at the
+    /// next prompt of the line editor. Returns what was shown on the terminal
+    /// too.
+    pub fn type_at_prompts(&self, fixture: &str, args: &[&str], keys: &[&str]) -> (Output, String) {
+        at_prompts(self.recorded(fixture, args), keys)
+    }
+
+    /// aic with `fixture` as the model, recording its requests in `recording/`.
+    pub fn recorded(&self, fixture: &str, args: &[&str]) -> Command {
+        let mut command = self.command(args);
+        command
+            .env("AIC_RECORD", self.dir.join("recording"))
+            .arg("--model")
+            .arg(format!("mock:{}", fixture_path(fixture).display()));
+        command
+    }
+
+    /// The last user message of recorded request `number`.
+    pub fn sent(&self, number: usize) -> String {
+        let path = self.dir.join(format!("recording/{number:04}.json"));
+        let exchange: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let messages = exchange["request"]["messages"].as_array().unwrap();
+        messages
+            .iter()
+            .rev()
+            .find(|message| message["role"] == "User")
+            .map(|message| message["content"].to_string())
+            .unwrap()
+    }
+
+    pub fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_aic"));
+        command
+            .args(args)
+            .current_dir(&self.dir)
+            .env("HOME", &self.dir)
+            .env("XDG_CONFIG_HOME", self.dir.join("config"))
+            .env("XDG_DATA_HOME", self.dir.join("data"))
+            .env("NO_COLOR", "1")
+            .env_remove("AIC_MODEL")
+            .env_remove("AIC_YES")
+            .env_remove("AIC_RECORD")
+            .env_remove("AIC_REPLAY")
+            .env_remove("AIC_BASE_URL");
+        // Without a controlling terminal, prompts fall back to stdin instead of
+        // waiting on the terminal running the tests.
+        unsafe {
+            command.pre_exec(|| {
+                libc::setsid();
+                Ok(())
+            });
+        }
+        command
+    }
+
+    /// Stores a chat session as aic saves it, with one question and its answer,
+    /// and returns its path.
+    pub fn save_session(&self, id: &str, updated_at: u64, question: &str, answer: &str) -> PathBuf {
+        let dir = self.dir.join("data/aic/sessions");
+        std::fs::create_dir_all(&dir).unwrap();
+        let session = serde_json::json!({
+            "id": id,
+            "title": question,
+            "model": format!("mock:{}", fixture_path("session.toml").display()),
+            "system": "You are aic.",
+            "created_at": updated_at,
+            "updated_at": updated_at,
+            "messages": [
+                { "role": "User", "content": { "Text": question }, "options": null },
+                { "role": "Assistant", "content": { "Text": answer }, "options": null },
+            ],
+        });
+        let path = dir.join(format!("{id}.json"));
+        std::fs::write(&path, session.to_string()).unwrap();
+        path
+    }
+
+    pub fn sessions(&self) -> Vec<String> {
+        let dir = self.dir.join("data/aic/sessions");
+        std::fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub fn output(mut command: Command, stdin: &str) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Runs `command` with a pseudo-terminal as its controlling terminal, on which
+/// `typed` is entered, and `stdin` piped in. Returns the output and what was
+/// shown on the terminal.
+fn on_terminal(command: Command, typed: &str, stdin: &str) -> (Output, String) {
+    let (mut child, mut master) = spawn_on_terminal(command);
+    master.write_all(typed.as_bytes()).unwrap();
+    let mut terminal = master.try_clone().unwrap();
+    let shown = std::thread::spawn(move || {
+        let mut shown = Vec::new();
+        // Reading fails with EIO once aic has closed the terminal.
+        let _ = terminal.read_to_end(&mut shown);
+        String::from_utf8_lossy(&shown).into_owned()
+    });
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    drop(master);
+    (output, shown.join().unwrap())
+}
+
+/// The line editor turns on bracketed paste each time it starts reading the
+/// terminal in raw mode, so keys typed after it are not echoed or turned into
+/// signals.
+const EDITOR_READING: &str = "\x1b[?2004h";
+
+/// Runs `command` on a pseudo-terminal with nothing piped in, typing each of
+/// `keys` once the line editor reads again. Returns the output and what was
+/// shown on the terminal.
+fn at_prompts(command: Command, keys: &[&str]) -> (Output, String) {
+    let (mut child, mut master) = spawn_on_terminal(command);
+    drop(child.stdin.take());
+    let mut terminal = master.try_clone().unwrap();
+    let (sender, chunks) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut chunk = [0; 4096];
+        // Reading fails with EIO once aic has closed the terminal.
+        while let Ok(read @ 1..) = terminal.read(&mut chunk) {
+            if sender.send(chunk[..read].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut shown = String::new();
+    for (typed, key) in keys.iter().enumerate() {
+        while shown.matches(EDITOR_READING).count() <= typed {
+            match chunks.recv_timeout(std::time::Duration::from_secs(10)) {
+                Ok(chunk) => shown.push_str(&String::from_utf8_lossy(&chunk)),
+                Err(_) => {
+                    let _ = child.kill();
+                    panic!("prompt {} never shown:\n{shown}", typed + 1);
+                }
+            }
+        }
+        master.write_all(key.as_bytes()).unwrap();
+    }
+    let output = child.wait_with_output().unwrap();
+    drop(master);
+    shown.extend(
+        chunks
+            .iter()
+            .map(|chunk| String::from_utf8_lossy(&chunk).into_owned()),
+    );
+    (output, shown)
+}
+
+/// Starts `command` with a new pty as its controlling terminal and piped
+/// stdio, returning the master end.
+fn spawn_on_terminal(mut command: Command) -> (Child, File) {
+    let (master, slave) = pty();
+    let slave_fd = slave.as_raw_fd();
+    // Make the pty the controlling terminal, i.e. what /dev/tty opens.
+    unsafe {
+        command.pre_exec(move || {
+            if libc::ioctl(slave_fd, libc::TIOCSCTTY, 0) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    drop(slave);
+    (child, master)
+}
+
+/// A pseudo-terminal: the master end for the test, the slave end for aic.
+fn pty() -> (File, OwnedFd) {
+    let (mut master, mut slave) = (0, 0);
+    let result = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null(),
+        )
+    };
+    assert_eq!(result, 0, "openpty failed");
+    unsafe { (File::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) }
+}
+
+pub fn fixture_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+pub fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
@@ -0,0 +1,249 @@
+//! Points aic at a stub server speaking the OpenAI chat-completions protocol,
+//! as a local Ollama, llama.cpp or vLLM server would.
+
+mod common;
+
+use common::{output, text, Sandbox};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// What the stub answers to one request.
+enum Reply {
+    /// A `chat.completion` object with this assistant message.
+    Message(&'static str),
+    /// A `text/event-stream` of `chat.completion.chunk` objects, one per piece.
+    Stream(&'static [&'static str]),
+}
+
+/// One request as received by the stub.
+struct Received {
+    head: String,
+    body: String,
+}
+
+/// Serves one reply per connection, in order, then stops listening.
+struct StubServer {
+    url: String,
+    received: Arc<Mutex<Vec<Received>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl StubServer {
+    fn start(replies: Vec<Reply>) -> StubServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&received);
+        let thread = std::thread::spawn(move || {
+            for reply in replies {
+                let Ok((stream, _)) = listener.accept() else {
+                    return;
+                };
+                let mut reader = BufReader::new(stream);
+                let request = read_request(&mut reader);
+                log.lock().unwrap().push(request);
+                reader
+                    .get_mut()
+                    .write_all(response(&reply).as_bytes())
+                    .unwrap();
+            }
+        });
+        StubServer {
+            url,
+            received,
+            thread: Some(thread),
+        }
+    }
+
+    /// Waits for every reply to be served and returns the requests.
+    fn finish(mut self) -> Vec<Received> {
+        self.thread.take().unwrap().join().unwrap();
+        std::mem::take(&mut *self.received.lock().unwrap())
+    }
+}
+
+fn read_request(reader: &mut BufReader<std::net::TcpStream>) -> Received {
+    let mut head = String::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line == "\r\n" || line.is_empty() {
+            break;
+        }
+        head.push_str(&line);
+    }
+    let length = head
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("content-length")
+                .then(|| value.trim().parse::<usize>().unwrap())
+        })
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+    Received {
+        head,
+        body: String::from_utf8(body).unwrap(),
+    }
+}
+
+const USAGE: &str = r#"{"prompt_tokens":11,"completion_tokens":7,"total_tokens":18}"#;
+
+fn response(reply: &Reply) -> String {
+    let (content_type, body) = match reply {
+        Reply::Message(message) => (
+            "application/json",
+            format!(
+                r#"{{"id":"stub","object":"chat.completion","created":0,"model":"stub","choices":[{{"index":0,"message":{message},"finish_reason":"stop"}}],"usage":{USAGE}}}"#
+            ),
+        ),
+        Reply::Stream(pieces) => {
+            let mut body = String::new();
+            for piece in pieces.iter() {
+                let delta = serde_json::json!({ "content": piece });
+                body.push_str(&format!(
+                    "data: {{\"id\":\"stub\",\"object\":\"chat.completion.chunk\",\"created\":0,\"model\":\"stub\",\"choices\":[{{\"index\":0,\"delta\":{delta},\"finish_reason\":null}}]}}\n\n"
+                ));
+            }
+            body.push_str(&format!(
+                "data: {{\"id\":\"stub\",\"object\":\"chat.completion.chunk\",\"created\":0,\"model\":\"stub\",\"choices\":[],\"usage\":{USAGE}}}\n\n"
+            ));
+            body.push_str("data: [DONE]\n\n");
+            ("text/event-stream", body)
+        }
+    };
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+/// aic with no provider key in its environment, pointed at `url`.
+fn offline(sandbox: &Sandbox, url: &str, args: &[&str]) -> Command {
+    let mut command = sandbox.command(args);
+    command.env("AIC_BASE_URL", url);
+    for key in [
+        "OPENAI_API_KEY",
+        "ANTHROPIC_API_KEY",
+        "GEMINI_API_KEY",
+        "GROQ_API_KEY",
+        "COHERE_API_KEY",
+        "XAI_API_KEY",
+        "DEEPSEEK_API_KEY",
+        "MODEL_API_KEY",
+    ] {
+        command.env_remove(key);
+    }
+    command
+}
+
+#[test]
+fn ask_runs_against_the_endpoint_without_an_api_key() {
+    let sandbox = Sandbox::new("endpoint-ask");
+    let server = StubServer::start(vec![
+        Reply::Message(
+            r#"{"role":"assistant","content":null,"tool_calls":[{"id":"call_1","type":"function","function":{"name":"run_commandes","arguments":"{\"command\":\"echo stub-ran\",\"info\":\"check the stub\"}"}}]}"#,
+        ),
+        Reply::Message(r#"{"role":"assistant","content":"The local model is answering."}"#),
+    ]);
+    let mut command = offline(&sandbox, &server.url, &["ask", "is it local?"]);
+    command.args(["--model", "llama3.1:8b"]);
+    let output = output(command, "");
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+    let received = server.finish();
+
+    assert!(
+        stdout.contains("Executing: echo stub-ran"),
+        "{stdout}\n{stderr}"
+    );
+    assert!(stdout.contains("The local model is answering."), "{stdout}");
+    assert!(
+        stderr.contains("2 requests, 22 prompt + 14 completion tokens"),
+        "{stderr}"
+    );
+
+    assert_eq!(received.len(), 2);
+    assert!(
+        received[0].head.starts_with("POST /v1/chat/completions "),
+        "{}",
+        received[0].head
+    );
+    assert!(
+        received[0].body.contains(r#""model":"llama3.1:8b""#),
+        "{}",
+        received[0].body
+    );
+    assert!(received[0].body.contains("is it local?"));
+    // The tool result goes back to the server.
+    assert!(
+        received[1].body.contains("stub-ran"),
+        "{}",
+        received[1].body
+    );
+}
+
+#[test]
+fn chat_streams_from_the_endpoint() {
+    let sandbox = Sandbox::new("endpoint-chat");
+    let server = StubServer::start(vec![Reply::Stream(&["Hello ", "from ", "the stub."])]);
+    let mut command = offline(&sandbox, &server.url, &["chat"]);
+    command.args(["--model", "qwen2.5-coder"]);
+    let output = output(command, "hi\n/usage\nexit\n");
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+    let received = server.finish();
+
+    assert!(
+        stdout.contains("Hello from the stub."),
+        "{stdout}\n{stderr}"
+    );
+    assert!(
+        stdout.contains("1 request, 11 prompt + 7 completion tokens"),
+        "{stdout}"
+    );
+    assert!(
+        received[0].body.contains(r#""stream":true"#),
+        "{}",
+        received[0].body
+    );
+}
+
+#[test]
+fn endpoint_key_comes_from_the_configured_variable() {
+    let sandbox = Sandbox::new("endpoint-key");
+    let config = sandbox.dir.join("config/aic");
+    std::fs::create_dir_all(&config).unwrap();
+    std::fs::write(
+        config.join("config.toml"),
+        "model = \"mistral\"\n\n[endpoint]\napi_key_env = \"LOCAL_LLM_KEY\"\n",
+    )
+    .unwrap();
+
+    let server = StubServer::start(vec![Reply::Message(
+        r#"{"role":"assistant","content":"Authorized."}"#,
+    )]);
+    let mut command = offline(&sandbox, &server.url, &["ask", "hello"]);
+    command.env("LOCAL_LLM_KEY", "secret-token");
+    let stdout = text(&output(command, "").stdout);
+    let received = server.finish();
+
+    assert!(stdout.contains("Authorized."), "{stdout}");
+    let authorization = received[0]
+        .head
+        .lines()
+        .find(|line| line.to_ascii_lowercase().starts_with("authorization:"))
+        .unwrap_or_default();
+    assert!(
+        authorization.ends_with("Bearer secret-token"),
+        "{}",
+        received[0].head
+    );
+
+    let mut command = offline(&sandbox, "http://127.0.0.1:9/v1", &["ask", "hello"]);
+    command.env_remove("LOCAL_LLM_KEY");
+    let stderr = text(&output(command, "").stderr);
+    assert!(stderr.contains("set LOCAL_LLM_KEY"), "{stderr}");
+}
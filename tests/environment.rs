@@ -0,0 +1,41 @@
+//! `ask` prompts start with a summary of the environment unless `--no-context`.
+
+mod common;
+
+use common::{output, Sandbox};
+
+#[test]
+fn ask_sends_the_environment_first() {
+    let sandbox = Sandbox::new("environment");
+    std::fs::write(sandbox.dir.join("Cargo.toml"), "[package]\n").unwrap();
+    std::fs::write(sandbox.dir.join("package.json"), "{}\n").unwrap();
+
+    output(
+        sandbox.recorded("environment.toml", &["ask", "build it"]),
+        "",
+    );
+    let prompt = sandbox.sent(1);
+    assert!(
+        prompt.contains("Environment (collected by aic):"),
+        "{prompt}"
+    );
+    assert!(prompt.contains(&format!("- Working directory: {}", sandbox.dir.display())));
+    assert!(
+        prompt.contains("- Project: Rust (Cargo.toml), Node.js (package.json)"),
+        "{prompt}"
+    );
+    assert!(prompt.contains("- Tools: git"), "{prompt}");
+    assert!(prompt.contains("build it"));
+}
+
+#[test]
+fn no_context_sends_the_prompt_alone() {
+    let sandbox = Sandbox::new("environment-off");
+    output(
+        sandbox.recorded("environment.toml", &["ask", "build it", "--no-context"]),
+        "",
+    );
+    let prompt = sandbox.sent(1);
+    assert!(!prompt.contains("Environment"), "{prompt}");
+    assert_eq!(prompt, r#"{"Text":"build it"}"#);
+}
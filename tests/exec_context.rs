@@ -0,0 +1,51 @@
+//! Commands of one session share the directory and exported variables the
+//! previous command left behind.
+
+mod common;
+
+use common::{text, Sandbox};
+
+#[test]
+fn cd_and_export_carry_over_to_later_commands() {
+    let sandbox = Sandbox::new("exec-context");
+    let output = sandbox.run(
+        "exec_context.toml",
+        &["chat", "--tools"],
+        "work in sub\nyes\nmark it\nyes\nexit\n",
+    );
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+    assert!(stdout.contains("Marked."), "{stdout}\n{stderr}");
+
+    let sub = sandbox.dir.join("sub");
+    // The second confirmation shows where the command will run.
+    assert!(
+        stdout.contains(&format!("Directory: {}\n", sub.display())),
+        "{stdout}"
+    );
+    let marker = std::fs::read_to_string(sub.join("marker")).unwrap();
+    assert_eq!(marker, "var=kept\n");
+}
+
+#[test]
+fn policy_paths_are_resolved_in_the_directory_commands_run_in() {
+    let sandbox = Sandbox::new("exec-context-policy");
+    let sub = sandbox.dir.join("sub");
+    std::fs::write(
+        sandbox.dir.join(".aic-policy.toml"),
+        format!("[[deny]]\npaths = [\"{}/marker\"]\n", sub.display()),
+    )
+    .unwrap();
+
+    let output = sandbox.run(
+        "exec_context_deny.toml",
+        &["chat", "--tools"],
+        "work in sub\nyes\nmark it\nexit\n",
+    );
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+    assert!(
+        stdout.contains("The policy does not allow it."),
+        "{stdout}\n{stderr}"
+    );
+    assert!(stderr.contains("Refused"), "{stderr}");
+    assert!(!sub.join("marker").exists());
+}
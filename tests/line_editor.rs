@@ -0,0 +1,60 @@
+//! The chat prompt: multi-line input, history and editing keys.
+
+mod common;
+
+use common::{output, text, Sandbox};
+
+#[test]
+fn a_fenced_block_is_sent_as_one_message() {
+    let sandbox = Sandbox::new("editor-fence");
+    let output = output(
+        sandbox.recorded("chat_fence.toml", &["chat"]),
+        "```\nline one\nline two\n```\nexit\n",
+    );
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+
+    assert!(stdout.contains("Got both lines."), "{stdout}\n{stderr}");
+    assert!(
+        sandbox.sent(1).contains("```\\nline one\\nline two\\n```"),
+        "{}",
+        sandbox.sent(1)
+    );
+}
+
+#[test]
+fn history_is_saved_and_recalled_with_up() {
+    let sandbox = Sandbox::new("editor-history");
+    let output = sandbox.run("chat.toml", &["chat"], "hello\n");
+    assert!(output.status.success(), "{}", text(&output.stderr));
+    let history = std::fs::read_to_string(sandbox.dir.join("data/aic/history")).unwrap();
+    assert!(history.lines().any(|line| line == "hello"), "{history}");
+
+    let (output, shown) = sandbox.type_at_prompts("chat.toml", &["chat"], &["\x1b[A\r", "exit\r"]);
+    let stdout = text(&output.stdout);
+
+    assert!(
+        stdout.contains("Hi there, how can I help?"),
+        "{stdout}\n{shown}"
+    );
+}
+
+#[test]
+fn alt_enter_adds_a_line_and_ctrl_c_discards_the_input() {
+    let sandbox = Sandbox::new("editor-keys");
+    let (output, shown) = sandbox.type_at_prompts(
+        "chat_fence.toml",
+        &["chat"],
+        &["line one\x1b\rline two\r", "never sent\x03", "exit\r"],
+    );
+    let stdout = text(&output.stdout);
+
+    assert!(stdout.contains("Got both lines."), "{stdout}\n{shown}");
+    assert!(
+        sandbox.sent(1).contains("line one\\nline two"),
+        "{}",
+        sandbox.sent(1)
+    );
+    assert!(!sandbox.dir.join("recording/0002.json").exists());
+    let history = std::fs::read_to_string(sandbox.dir.join("data/aic/history")).unwrap();
+    assert!(!history.contains("never sent"), "{history}");
+}
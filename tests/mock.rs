@@ -0,0 +1,280 @@
+//! Drives `aic ask` and `aic chat` against the scripted mock backend.
+
+mod common;
+
+use common::{fixture_path, output, text, Sandbox};
+
+#[test]
+fn ask_prints_the_answer_and_records_usage() {
+    let sandbox = Sandbox::new("ask-answer");
+    let output = sandbox.run("ask_answer.toml", &["ask", "What is aic?"], "");
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+
+    assert!(
+        stdout.contains("aic is a command-line assistant."),
+        "{stdout}"
+    );
+    assert!(
+        stderr.contains("1 request, 120 prompt + 8 completion tokens"),
+        "{stderr}"
+    );
+    let ledger = std::fs::read_to_string(sandbox.dir.join("data/aic/usage.jsonl")).unwrap();
+    assert!(ledger.contains("\"command\":\"ask\""), "{ledger}");
+}
+
+#[test]
+fn ask_runs_tool_calls_and_sends_back_their_output() {
+    let sandbox = Sandbox::new("ask-tool");
+    let output = sandbox.run("ask_tool.toml", &["ask", "print the marker"], "");
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+
+    assert!(stdout.contains("Executing: echo mock-output"), "{stdout}");
+    assert!(
+        stdout.contains("The command printed the marker."),
+        "{stdout}\n{stderr}"
+    );
+    assert!(stderr.contains("2 requests"), "{stderr}");
+}
+
+#[test]
+fn ask_tells_the_model_when_a_command_is_declined() {
+    let sandbox = Sandbox::new("ask-declined");
+    let (output, _) =
+        sandbox.run_on_terminal("ask_declined.toml", &["ask", "clean the build"], "no\n", "");
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+
+    assert!(stdout.contains("Command execution cancelled"), "{stdout}");
+    assert!(
+        stdout.contains("Nothing was removed."),
+        "{stdout}\n{stderr}"
+    );
+}
+
+#[test]
+fn ask_declines_a_command_at_the_end_of_input() {
+    let sandbox = Sandbox::new("ask-declined-eof");
+    // With no terminal and nothing more on stdin, the confirmation gets no answer.
+    let output = sandbox.run("ask_declined.toml", &["ask", "clean the build"], "");
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+
+    assert!(stdout.contains("rm -rf ./build"), "{stdout}");
+    assert!(stdout.contains("Command execution cancelled"), "{stdout}");
+    assert!(
+        stdout.contains("Nothing was removed."),
+        "{stdout}\n{stderr}"
+    );
+}
+
+#[test]
+fn ask_reports_a_fixture_running_out_of_responses() {
+    let sandbox = Sandbox::new("ask-exhausted");
+    let output = sandbox.run("ask_exhausted.toml", &["ask", "do nothing"], "");
+    let stderr = text(&output.stderr);
+
+    assert!(
+        stderr.contains("no scripted response left for request 2"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn chat_streams_answers_and_saves_the_session() {
+    let sandbox = Sandbox::new("chat");
+    let input = "hello\nsecond question\n/usage\nexit\n";
+    let output = sandbox.run("chat.toml", &["chat"], input);
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+
+    assert!(
+        stdout.contains("Hi there, how can I help?"),
+        "{stdout}\n{stderr}"
+    );
+    assert!(stdout.contains("This is the second answer."), "{stdout}");
+    assert!(stdout.contains("2 requests"), "{stdout}");
+
+    let sessions = sandbox.sessions();
+    assert_eq!(sessions.len(), 1);
+    assert!(
+        sessions[0].contains("\"title\": \"hello\""),
+        "{}",
+        sessions[0]
+    );
+    assert!(sessions[0].contains("This is the second answer."));
+}
+
+#[test]
+fn chat_with_tools_runs_commands() {
+    let sandbox = Sandbox::new("chat-tools");
+    let output = sandbox.run(
+        "chat_tools.toml",
+        &["chat", "--tools"],
+        "which directory am I in?\nexit\n",
+    );
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+
+    assert!(stdout.contains("Executing: pwd"), "{stdout}");
+    assert!(
+        stdout.contains("You are in the project directory."),
+        "{stdout}\n{stderr}"
+    );
+}
+
+#[test]
+fn chat_survives_a_failed_request() {
+    let sandbox = Sandbox::new("chat-error");
+    let output = sandbox.run("chat_error.toml", &["chat"], "hi\nagain\nexit\n");
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+
+    assert!(stderr.contains("provider unavailable"), "{stderr}");
+    assert!(stdout.contains("Back online."), "{stdout}\n{stderr}");
+}
+
+#[test]
+fn recorded_exchanges_replay_without_the_model() {
+    let sandbox = Sandbox::new("record");
+    let recording = sandbox.dir.join("recording");
+    let mut command = sandbox.command(&["ask", "print the marker"]);
+    command
+        .env("AIC_RECORD", &recording)
+        .arg("--model")
+        .arg(format!("mock:{}", fixture_path("ask_tool.toml").display()));
+    let recorded = text(&output(command, "").stdout);
+    assert!(recording.join("0001.json").is_file());
+    assert!(recording.join("0002.json").is_file());
+
+    // No fixture and no API key: every reply comes from the recording.
+    let mut command = sandbox.command(&["ask", "print the marker"]);
+    command
+        .env("AIC_REPLAY", &recording)
+        .arg("--model")
+        .arg("gpt-4o-mini")
+        .env_remove("OPENAI_API_KEY")
+        .env_remove("MODEL_API_KEY");
+    let output = output(command, "");
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+
+    assert_eq!(stdout, recorded, "{stderr}");
+    assert!(stdout.contains("The command printed the marker."));
+}
+
+#[test]
+fn chat_resumes_a_session_by_id_prefix_or_last() {
+    let sandbox = Sandbox::new("chat-find");
+    sandbox.save_session("abc123", 1000, "first question", "first answer");
+    sandbox.save_session("abd456", 3000, "second question", "second answer");
+    sandbox.save_session("xyz789", 2000, "third question", "third answer");
+
+    for (id, title) in [
+        ("abc123", "first question"),
+        ("abc", "first question"),
+        ("last", "second question"),
+        ("x", "third question"),
+    ] {
+        let output = sandbox.run("chat.toml", &["chat", "--resume", id], "exit\n");
+        let stdout = text(&output.stdout);
+        assert!(
+            stdout.contains(&format!("Resuming \"{title}\"")),
+            "{id}: {stdout}\n{}",
+            text(&output.stderr)
+        );
+    }
+
+    let output = sandbox.run("chat.toml", &["chat", "--resume", "ab"], "exit\n");
+    let stderr = text(&output.stderr);
+    assert!(
+        stderr.contains("2 chat sessions start with \"ab\", use more characters"),
+        "{stderr}"
+    );
+    let output = sandbox.run("chat.toml", &["chat", "--resume", "nope"], "exit\n");
+    let stderr = text(&output.stderr);
+    assert!(stderr.contains("No chat session \"nope\""), "{stderr}");
+}
+
+#[test]
+fn chat_lists_and_deletes_sessions() {
+    let sandbox = Sandbox::new("chat-list");
+    let stdout = text(&output(sandbox.command(&["chat", "--list"]), "").stdout);
+    assert!(stdout.contains("No saved chat sessions"), "{stdout}");
+
+    sandbox.save_session("abc123", 1000, "first question", "first answer");
+    sandbox.save_session("abd456", 3000, "second question", "second answer");
+    let third = sandbox.save_session("xyz789", 2000, "third question", "third answer");
+
+    let stdout = text(&output(sandbox.command(&["chat", "--list"]), "").stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "{stdout}");
+    // Most recently updated first.
+    for (line, id) in lines.iter().zip(["abd456", "xyz789", "abc123"]) {
+        assert!(line.starts_with(id), "{stdout}");
+    }
+    assert!(lines[0].contains("2 msgs") && lines[0].ends_with("second question"));
+
+    let stdout = text(&output(sandbox.command(&["chat", "--delete", "xyz"]), "").stdout);
+    assert!(
+        stdout.contains("Deleted session xyz789 \"third question\""),
+        "{stdout}"
+    );
+    assert!(!third.exists());
+    let stdout = text(&output(sandbox.command(&["chat", "--list"]), "").stdout);
+    assert!(!stdout.contains("xyz789"), "{stdout}");
+
+    let stderr = text(&output(sandbox.command(&["chat", "--delete", "ab"]), "").stderr);
+    assert!(stderr.contains("2 chat sessions start with"), "{stderr}");
+    assert_eq!(sandbox.sessions().len(), 2);
+}
+
+#[test]
+fn chat_retry_undo_and_write_work_on_the_last_exchange() {
+    let sandbox = Sandbox::new("chat-retry");
+    let input =
+        "hello\n/write first.txt\n/retry\n/write second.txt\n/undo\n/write none.txt\nexit\n";
+    let output = sandbox.run("chat_retry.toml", &["chat"], input);
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+
+    assert!(stdout.contains("Second try."), "{stdout}\n{stderr}");
+    let written = |name: &str| std::fs::read_to_string(sandbox.dir.join(name)).ok();
+    assert_eq!(written("first.txt").as_deref(), Some("First try."));
+    // The retried answer replaces the first one.
+    assert_eq!(written("second.txt").as_deref(), Some("Second try."));
+    assert!(stdout.contains("Dropped the last exchange"), "{stdout}");
+    assert!(stderr.contains("No answer to write yet"), "{stderr}");
+    assert_eq!(written("none.txt"), None);
+
+    let sessions = sandbox.sessions();
+    assert_eq!(sessions.len(), 1);
+    // `/undo` dropped the only exchange.
+    assert!(sessions[0].contains("\"messages\": []"), "{}", sessions[0]);
+}
+
+#[test]
+fn chat_load_continues_a_saved_or_exported_session() {
+    let sandbox = Sandbox::new("chat-load");
+    sandbox.save_session("abc123", 1000, "first question", "first answer");
+    let exported = sandbox.save_session("def456", 2000, "exported question", "exported answer");
+    std::fs::rename(&exported, sandbox.dir.join("exported.json")).unwrap();
+
+    let input = "/load abc\nfollow up\n/load exported.json\nfollow up on the file\nexit\n";
+    let output = output(sandbox.recorded("chat.toml", &["chat"]), input);
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+
+    assert!(
+        stdout.contains("Resuming \"first question\""),
+        "{stdout}\n{stderr}"
+    );
+    assert_eq!(
+        stdout.matches("Following up on the loaded chat.").count(),
+        2,
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("Resuming \"exported question\""),
+        "{stdout}"
+    );
+
+    // Each request carries the history of the session it continues.
+    let request = |number: usize| {
+        std::fs::read_to_string(sandbox.dir.join(format!("recording/{number:04}.json"))).unwrap()
+    };
+    assert!(request(1).contains("first answer"), "{}", request(1));
+    assert!(request(2).contains("exported answer"), "{}", request(2));
+    assert!(!request(2).contains("first answer"), "{}", request(2));
+}
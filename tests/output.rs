@@ -0,0 +1,102 @@
+//! `--output json`: stdout holds exactly one JSON document.
+
+mod common;
+
+use common::{output, text, Sandbox};
+use serde_json::Value;
+
+fn document(stdout: &[u8]) -> Value {
+    let stdout = text(stdout);
+    serde_json::from_str(&stdout).unwrap_or_else(|e| panic!("{e}: {stdout}"))
+}
+
+#[test]
+fn ask_reports_commands_answer_and_usage() {
+    let sandbox = Sandbox::new("output-ask");
+    let output = sandbox.run(
+        "ask_tool.toml",
+        &["ask", "--output", "json", "print the marker"],
+        "",
+    );
+    let doc = document(&output.stdout);
+
+    assert_eq!(doc["answer"], "The command printed the marker.");
+    assert_eq!(doc["error"], Value::Null);
+    assert_eq!(doc["usage"]["requests"], 2);
+    let command = &doc["commands"][0];
+    assert_eq!(command["command"], "echo mock-output");
+    assert_eq!(command["info"], "print a marker");
+    assert_eq!(command["cwd"], sandbox.dir.display().to_string());
+    assert_eq!(command["security"]["tier"], 1);
+    assert_eq!(command["security"]["decision"]["action"], "run");
+    assert_eq!(command["confirmed"], Value::Null);
+    assert_eq!(command["exit_code"], 0);
+    assert_eq!(command["stdout"], "mock-output\n");
+    assert!(command["duration_ms"].is_u64());
+}
+
+#[test]
+fn ask_reports_a_declined_command() {
+    let sandbox = Sandbox::new("output-declined");
+    let (output, shown) = sandbox.run_on_terminal(
+        "ask_declined.toml",
+        &["ask", "--output", "json", "clean the build"],
+        "no\n",
+        "",
+    );
+    let doc = document(&output.stdout);
+    // The confirmation prompt stays off stdout.
+    assert!(shown.contains("rm -rf ./build"), "{shown}");
+
+    let command = &doc["commands"][0];
+    assert_eq!(command["confirmed"], false);
+    assert_eq!(command["executed"], false);
+    assert_eq!(doc["answer"], "Nothing was removed.");
+}
+
+#[test]
+fn ask_reports_a_command_declined_at_the_end_of_input() {
+    let sandbox = Sandbox::new("output-declined-eof");
+    let output = sandbox.run(
+        "ask_declined.toml",
+        &["ask", "--output", "json", "clean the build"],
+        "",
+    );
+    let doc = document(&output.stdout);
+    // Without a terminal the confirmation prompt goes to stderr.
+    assert!(text(&output.stderr).contains("rm -rf ./build"));
+
+    let command = &doc["commands"][0];
+    assert_eq!(command["security"]["tier"], 3);
+    assert_eq!(command["confirmed"], false);
+    assert_eq!(command["executed"], false);
+    assert_eq!(doc["answer"], "Nothing was removed.");
+}
+
+#[test]
+fn run_reports_exit_status_and_both_streams() {
+    let sandbox = Sandbox::new("output-run");
+    let command = sandbox.command(&[
+        "run",
+        "--output",
+        "json",
+        "-y",
+        "echo out; echo err >&2; exit 3",
+    ]);
+    let doc = document(&output(command, "").stdout);
+
+    assert_eq!(doc["status"], "exited");
+    assert_eq!(doc["exit_code"], 3);
+    assert_eq!(doc["stdout"], "out\n");
+    assert_eq!(doc["stderr"], "err\n");
+    assert_eq!(doc["confirmed"], Value::Null);
+    assert_eq!(doc["security"]["label"], "user-space change");
+}
+
+#[test]
+fn chat_rejects_json_output() {
+    let sandbox = Sandbox::new("output-chat");
+    let output = output(sandbox.command(&["chat", "--output", "json"]), "");
+    assert!(text(&output.stdout).is_empty());
+    assert!(text(&output.stderr).contains("only supported by `aic ask` and `aic run`"));
+}
@@ -0,0 +1,109 @@
+//! `aic run` applies the same security checks as the commands the model proposes.
+
+mod common;
+
+use common::{output, text, Sandbox};
+use serde_json::Value;
+
+const DENY_TOUCH: &str = r#"
+[[deny]]
+program = "touch"
+reason = "no new files"
+"#;
+
+#[test]
+fn run_refuses_a_denied_command() {
+    let sandbox = Sandbox::new("run-deny");
+    std::fs::write(sandbox.dir.join(".aic-policy.toml"), DENY_TOUCH).unwrap();
+
+    for args in [&["run", "touch marker"][..], &["run", "-y", "touch marker"]] {
+        let output = output(sandbox.command(args), "yes\n");
+        let stderr = text(&output.stderr);
+        assert!(stderr.contains("Refused"), "{stderr}");
+        assert!(stderr.contains("no new files"), "{stderr}");
+        assert!(!sandbox.dir.join("marker").exists(), "{args:?} ran");
+    }
+}
+
+#[test]
+fn run_reports_a_refused_command_as_json() {
+    let sandbox = Sandbox::new("run-deny-json");
+    std::fs::write(sandbox.dir.join(".aic-policy.toml"), DENY_TOUCH).unwrap();
+
+    let command = sandbox.command(&["run", "--output", "json", "-y", "touch marker"]);
+    let stdout = text(&output(command, "yes\n").stdout);
+    let doc: Value = serde_json::from_str(&stdout).unwrap_or_else(|e| panic!("{e}: {stdout}"));
+
+    assert_eq!(doc["executed"], false);
+    assert_eq!(doc["confirmed"], Value::Null);
+    assert_eq!(doc["security"]["decision"]["action"], "refuse");
+    assert!(
+        doc["error"].as_str().unwrap().starts_with("refused: "),
+        "{doc}"
+    );
+    assert!(!sandbox.dir.join("marker").exists());
+}
+
+#[test]
+fn run_asks_before_destructive_commands_despite_yes() {
+    let sandbox = Sandbox::new("run-confirm");
+    let victim = sandbox.dir.join("victim");
+    std::fs::write(&victim, "keep me").unwrap();
+
+    let output = output(sandbox.command(&["run", "-y", "rm victim"]), "no\n");
+    assert!(text(&output.stdout).contains("Exiting"));
+    assert!(victim.exists());
+}
+
+#[test]
+fn run_needs_an_explicit_yes() {
+    let sandbox = Sandbox::new("run-yes");
+
+    for answer in ["\n", "sure\n", ""] {
+        output(sandbox.command(&["run", "touch marker"]), answer);
+        assert!(!sandbox.dir.join("marker").exists(), "{answer:?} ran");
+    }
+    output(sandbox.command(&["run", "touch marker"]), "Y\n");
+    assert!(sandbox.dir.join("marker").exists());
+}
+
+#[test]
+fn project_files_cannot_loosen_the_checks() {
+    let sandbox = Sandbox::new("run-project-trust");
+    std::fs::write(
+        sandbox.dir.join(".aic-policy.toml"),
+        "[[allow]]\nregex = \".*\"\n",
+    )
+    .unwrap();
+    let stderr = text(&output(sandbox.command(&["run", "-y", "touch marker"]), "").stderr);
+    assert!(
+        stderr.contains("allow rules are only read from the user policy file"),
+        "{stderr}"
+    );
+
+    std::fs::remove_file(sandbox.dir.join(".aic-policy.toml")).unwrap();
+    for setting in ["yes = true", "[security]\nallow_system_level = true"] {
+        std::fs::write(sandbox.dir.join(".aic.toml"), setting).unwrap();
+        let stderr = text(&output(sandbox.command(&["run", "touch marker"]), "").stderr);
+        assert!(
+            stderr.contains("can only be set in the user config"),
+            "{setting}: {stderr}"
+        );
+    }
+    assert!(!sandbox.dir.join("marker").exists());
+}
+
+#[test]
+fn project_files_can_make_the_checks_stricter() {
+    let sandbox = Sandbox::new("run-project-stricter");
+    std::fs::write(
+        sandbox.dir.join(".aic-policy.toml"),
+        "[[confirm]]\nprogram = \"touch\"\n",
+    )
+    .unwrap();
+    std::fs::write(sandbox.dir.join(".aic.toml"), "yes = false\n").unwrap();
+
+    let output = output(sandbox.command(&["run", "-y", "touch marker"]), "no\n");
+    assert!(text(&output.stdout).contains("Exiting"));
+    assert!(!sandbox.dir.join("marker").exists());
+}
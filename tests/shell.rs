@@ -0,0 +1,52 @@
+//! `shell = "persistent"` runs the commands of a session in one long-lived
+//! shell, started again when it exits.
+
+mod common;
+
+use common::{output, text, Sandbox};
+
+#[test]
+fn persistent_shell_keeps_functions_and_recovers() {
+    let sandbox = Sandbox::new("shell-persistent");
+    let mut command = sandbox.command(&["ask", "-y", "use one shell"]);
+    command
+        .env("AIC_SHELL", "persistent")
+        .arg("--model")
+        .arg(format!(
+            "mock:{}",
+            common::fixture_path("shell_persistent.toml").display()
+        ));
+    let output = output(command, "");
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+
+    assert!(
+        stdout.contains("The shell was restarted."),
+        "{stdout}\n{stderr}"
+    );
+    // Functions and unexported variables live on between commands.
+    assert!(stdout.contains("hello from sub\nkept=local\n"), "{stdout}");
+    // A syntax error does not end the shell: `greet` still works after it.
+    assert!(stderr.contains("Unterminated"), "{stderr}");
+    assert_eq!(stdout.matches("hello from sub\n").count(), 2, "{stdout}");
+    assert!(
+        stderr.contains("[shell] Started a new shell in"),
+        "{stderr}"
+    );
+    // The new shell starts where the old one was, without its functions.
+    let sub = sandbox.dir.join("sub");
+    assert!(
+        stdout.contains(&format!("{}\ngreet-lost\n", sub.display())),
+        "{stdout}"
+    );
+    assert!(!stdout.contains("__aic_done"), "{stdout}");
+}
+
+#[test]
+fn one_shot_stays_the_default() {
+    let sandbox = Sandbox::new("shell-one-shot");
+    let output = sandbox.run("shell_persistent.toml", &["ask", "-y", "use one shell"], "");
+    let stdout = text(&output.stdout);
+
+    // Each command gets a fresh shell: the function and variable are gone.
+    assert!(stdout.contains("kept=\n"), "{stdout}");
+}
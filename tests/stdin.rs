@@ -0,0 +1,135 @@
+//! Piped stdin is attached to the prompt; confirmations then come from the
+//! terminal.
+
+mod common;
+
+use common::{output, text, Sandbox};
+use std::os::unix::net::UnixStream;
+use std::process::{Child, Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+fn spawn(mut command: Command, stdin: Stdio) -> Child {
+    command
+        .stdin(stdin)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap()
+}
+
+/// Waits for `child`, failing if it is still waiting for input after a few
+/// seconds.
+fn finished(mut child: Child) -> Output {
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while child.try_wait().unwrap().is_none() {
+        if Instant::now() > deadline {
+            let _ = child.kill();
+            panic!("aic kept waiting for stdin");
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn piped_input_is_attached_to_the_prompt() {
+    let sandbox = Sandbox::new("stdin-ask");
+    let output = sandbox.run(
+        "stdin_ask.toml",
+        &["ask", "why is this failing"],
+        "app[42]: write error: disk quota exceeded\n",
+    );
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+
+    assert!(stdout.contains("The disk is full."), "{stdout}\n{stderr}");
+}
+
+#[test]
+fn piped_input_keeps_the_end_when_too_long() {
+    let sandbox = Sandbox::new("stdin-truncated");
+    let mut command = sandbox.command(&["ask", "what happened"]);
+    command
+        .env("AIC_MAX_INPUT", "10")
+        .arg("--model")
+        .arg(format!(
+            "mock:{}",
+            common::fixture_path("stdin_truncated.toml").display()
+        ));
+    let output = output(command, "0123456789abcdefghijklmnopqrstu\n");
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+
+    assert!(
+        stdout.contains("Only the end of the log was sent."),
+        "{stdout}\n{stderr}"
+    );
+}
+
+#[test]
+fn binary_input_is_refused() {
+    let sandbox = Sandbox::new("stdin-binary");
+    let output = sandbox.run("stdin_ask.toml", &["ask", "what is this"], "\0\x01\x02");
+    let stderr = text(&output.stderr);
+
+    assert!(stderr.contains("Piped input looks binary"), "{stderr}");
+}
+
+#[test]
+fn confirmation_is_read_from_the_terminal_while_stdin_is_piped() {
+    let sandbox = Sandbox::new("stdin-confirm");
+    let (output, shown) = sandbox.run_on_terminal(
+        "stdin_confirm.toml",
+        &["ask", "record the failure"],
+        "yes\n",
+        "error: build failed\n",
+    );
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+
+    assert!(stdout.contains("Marked."), "{stdout}\n{stderr}");
+    assert!(sandbox.dir.join("created-by-aic").is_file());
+    assert!(
+        shown.contains("Are you sure you want to execute"),
+        "{shown}"
+    );
+}
+
+#[test]
+fn a_file_on_stdin_is_attached() {
+    let sandbox = Sandbox::new("stdin-file");
+    let log = sandbox.dir.join("app.log");
+    std::fs::write(&log, "app[42]: write error: disk quota exceeded\n").unwrap();
+    let command = sandbox.recorded("stdin_ask.toml", &["ask", "why is this failing"]);
+    let output = finished(spawn(command, std::fs::File::open(&log).unwrap().into()));
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+
+    assert!(stdout.contains("The disk is full."), "{stdout}\n{stderr}");
+}
+
+#[test]
+fn a_socket_on_stdin_is_not_read() {
+    let sandbox = Sandbox::new("stdin-socket");
+    // The other end stays open, so reading would never end.
+    let (stdin, _open) = UnixStream::pair().unwrap();
+    let command = sandbox.recorded("stdin_none.toml", &["ask", "is it up"]);
+    let output = finished(spawn(command, std::os::fd::OwnedFd::from(stdin).into()));
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+
+    assert!(stdout.contains("It is up."), "{stdout}\n{stderr}");
+    assert!(
+        !sandbox.sent(1).contains("Input piped"),
+        "{}",
+        sandbox.sent(1)
+    );
+}
+
+#[test]
+fn no_stdin_skips_a_pipe_left_open() {
+    let sandbox = Sandbox::new("stdin-open");
+    let command = sandbox.recorded("stdin_none.toml", &["ask", "is it up", "--no-stdin"]);
+    let mut child = spawn(command, Stdio::piped());
+    // Keep the write end open, as a parent that never closes it would.
+    let _open = child.stdin.take();
+    let output = finished(child);
+    let (stdout, stderr) = (text(&output.stdout), text(&output.stderr));
+
+    assert!(stdout.contains("It is up."), "{stdout}\n{stderr}");
+}